@@ -0,0 +1,286 @@
+use super::{Backend, BACKENDS};
+use std::cmp::Reverse;
+use std::env;
+use std::fmt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Session {
+    #[default]
+    Any,
+    Wayland,
+    Hyprland,
+    X11,
+}
+
+impl Session {
+    // A backend made for a narrower session fits it better than a general one.
+    fn specificity(self) -> u8 {
+        match self {
+            Session::Any => 0,
+            Session::Wayland | Session::X11 => 1,
+            Session::Hyprland => 2,
+        }
+    }
+}
+
+// What a backend needs before it can draw anything.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Requirements {
+    pub programs: &'static [&'static str],
+    pub session: Session,
+}
+
+// The graphical session hyprwall runs in, read from the environment.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Environment {
+    pub wayland: bool,
+    pub hyprland: bool,
+    pub x11: bool,
+}
+
+impl Environment {
+    pub fn detect() -> Self {
+        let set = |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty());
+        let session_type = env::var("XDG_SESSION_TYPE").unwrap_or_default();
+
+        let hyprland = set("HYPRLAND_INSTANCE_SIGNATURE");
+        let wayland = hyprland || set("WAYLAND_DISPLAY") || session_type == "wayland";
+        // DISPLAY is also set under Xwayland, where X11 wallpapers stay hidden
+        let x11 = !wayland && (set("DISPLAY") || session_type == "x11");
+
+        Self {
+            wayland,
+            hyprland,
+            x11,
+        }
+    }
+
+    // From a TTY or over SSH nothing is known, so no backend is ruled out.
+    pub fn is_known(&self) -> bool {
+        self.wayland || self.x11
+    }
+
+    fn supports(&self, session: Session) -> bool {
+        match session {
+            Session::Any => true,
+            _ if !self.is_known() => true,
+            Session::Wayland => self.wayland,
+            Session::Hyprland => self.hyprland,
+            Session::X11 => self.x11,
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.hyprland, self.wayland, self.x11) {
+            (true, _, _) => write!(f, "Hyprland"),
+            (_, true, _) => write!(f, "Wayland"),
+            (_, _, true) => write!(f, "X11"),
+            _ => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Availability {
+    Available,
+    Missing(Vec<&'static str>),
+    WrongSession(Session),
+}
+
+impl Availability {
+    pub fn is_available(&self) -> bool {
+        *self == Availability::Available
+    }
+}
+
+impl fmt::Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Availability::Available => write!(f, "available"),
+            Availability::Missing(programs) => {
+                write!(f, "{} not found in $PATH", programs.join(", "))
+            }
+            Availability::WrongSession(Session::Any) => write!(f, "unavailable"),
+            Availability::WrongSession(Session::Wayland) => write!(f, "needs a Wayland session"),
+            Availability::WrongSession(Session::Hyprland) => {
+                write!(f, "needs a Hyprland session")
+            }
+            Availability::WrongSession(Session::X11) => write!(f, "needs an X11 session"),
+        }
+    }
+}
+
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| {
+            path.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+fn installed(program: &str) -> bool {
+    find_program(program).is_some()
+}
+
+pub fn availability(backend: &dyn Backend, environment: &Environment) -> Availability {
+    availability_with(backend, environment, &installed)
+}
+
+fn availability_with(
+    backend: &dyn Backend,
+    environment: &Environment,
+    installed: &dyn Fn(&str) -> bool,
+) -> Availability {
+    let requirements = backend.requirements();
+    let missing: Vec<&'static str> = requirements
+        .programs
+        .iter()
+        .copied()
+        .filter(|program| !installed(program))
+        .collect();
+
+    if !missing.is_empty() {
+        Availability::Missing(missing)
+    } else if !environment.supports(requirements.session) {
+        Availability::WrongSession(requirements.session)
+    } else {
+        Availability::Available
+    }
+}
+
+pub fn pick(environment: &Environment) -> Option<&'static dyn Backend> {
+    rank(environment, &installed)
+}
+
+// The available backend made for the narrowest session wins, so native ones
+// come before catch-alls. Ties go to the one listed first in BACKENDS.
+fn rank(
+    environment: &Environment,
+    installed: &dyn Fn(&str) -> bool,
+) -> Option<&'static dyn Backend> {
+    BACKENDS
+        .iter()
+        .copied()
+        .filter(|backend| availability_with(*backend, environment, installed).is_available())
+        .min_by_key(|backend| Reverse(backend.requirements().session.specificity()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Hyprpaper;
+
+    const HYPRLAND: Environment = Environment {
+        wayland: true,
+        hyprland: true,
+        x11: false,
+    };
+    const WAYLAND: Environment = Environment {
+        wayland: true,
+        hyprland: false,
+        x11: false,
+    };
+    const X11: Environment = Environment {
+        wayland: false,
+        hyprland: false,
+        x11: true,
+    };
+    const UNKNOWN: Environment = Environment {
+        wayland: false,
+        hyprland: false,
+        x11: false,
+    };
+
+    const EVERYTHING: &[&str] = &[
+        "hyprpaper",
+        "hyprctl",
+        "swww",
+        "swww-daemon",
+        "swaybg",
+        "feh",
+        "setwallpaper",
+    ];
+
+    fn picked(environment: Environment, programs: &[&str]) -> Option<&'static str> {
+        rank(&environment, &|program| programs.contains(&program)).map(|backend| backend.name())
+    }
+
+    #[test]
+    fn hyprland_prefers_hyprpaper() {
+        assert_eq!(picked(HYPRLAND, EVERYTHING), Some("hyprpaper"));
+        assert_eq!(
+            picked(HYPRLAND, &["swww", "swww-daemon", "swaybg", "feh"]),
+            Some("swww")
+        );
+    }
+
+    #[test]
+    fn wayland_never_picks_feh() {
+        assert_eq!(picked(WAYLAND, EVERYTHING), Some("swww"));
+        assert_eq!(picked(WAYLAND, &["hyprpaper", "hyprctl", "feh"]), None);
+        assert_eq!(picked(WAYLAND, &["feh", "setwallpaper"]), Some("wallutils"));
+    }
+
+    #[test]
+    fn x11_never_picks_a_wayland_backend() {
+        assert_eq!(picked(X11, EVERYTHING), Some("feh"));
+        assert_eq!(
+            picked(
+                X11,
+                &["hyprpaper", "hyprctl", "swww", "swww-daemon", "swaybg"]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn ties_go_to_the_first_listed() {
+        assert_eq!(
+            picked(WAYLAND, &["swaybg", "swww", "swww-daemon"]),
+            Some("swww")
+        );
+        assert_eq!(picked(WAYLAND, &["swaybg", "swww"]), Some("swaybg"));
+    }
+
+    #[test]
+    fn every_program_is_required() {
+        assert_eq!(
+            availability_with(&Hyprpaper, &HYPRLAND, &|program| program == "hyprpaper"),
+            Availability::Missing(vec!["hyprctl"])
+        );
+        assert_eq!(
+            availability_with(&Hyprpaper, &WAYLAND, &|_| true),
+            Availability::WrongSession(Session::Hyprland)
+        );
+    }
+
+    #[test]
+    fn an_unknown_session_rules_nothing_out() {
+        for session in [
+            Session::Any,
+            Session::Wayland,
+            Session::Hyprland,
+            Session::X11,
+        ] {
+            assert!(UNKNOWN.supports(session), "{:?}", session);
+        }
+        assert_eq!(picked(UNKNOWN, EVERYTHING), Some("hyprpaper"));
+        assert_eq!(picked(UNKNOWN, &["feh"]), Some("feh"));
+    }
+
+    #[test]
+    fn a_known_session_only_supports_itself() {
+        assert!(WAYLAND.supports(Session::Any));
+        assert!(WAYLAND.supports(Session::Wayland));
+        assert!(!WAYLAND.supports(Session::Hyprland));
+        assert!(!WAYLAND.supports(Session::X11));
+        assert!(HYPRLAND.supports(Session::Wayland));
+        assert!(!X11.supports(Session::Wayland));
+    }
+}
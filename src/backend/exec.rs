@@ -0,0 +1,193 @@
+use crate::error::HyprwallError;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::process::{Output, Stdio};
+use tokio::process::Command as TokioCommand;
+
+// A program and its arguments, handed to the program as they are. Nothing goes
+// through a shell, so a path with quotes, `$(` or backticks is just a path.
+#[derive(Clone, Debug)]
+pub struct Cmd {
+    program: &'static str,
+    args: Vec<OsString>,
+    quiet: bool,
+}
+
+impl Cmd {
+    pub fn new(program: &'static str) -> Self {
+        Self {
+            program,
+            args: Vec::new(),
+            quiet: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
+    }
+
+    // Drops what the program writes to stderr.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    pub fn program(&self) -> &'static str {
+        self.program
+    }
+
+    fn command(&self) -> TokioCommand {
+        let mut command = TokioCommand::new(self.program);
+        command.args(&self.args);
+        if self.quiet {
+            command.stderr(Stdio::null());
+        }
+        command
+    }
+
+    // Runs to completion and hands back whatever the program printed, however
+    // it exited. Only failing to launch it is an error.
+    pub async fn output(&self) -> Result<Output, HyprwallError> {
+        self.command()
+            .output()
+            .await
+            .map_err(|e| self.launch_error(e, "execute command"))
+    }
+
+    // Runs to completion, failing with the program's output if it exits
+    // unsuccessfully.
+    pub async fn run(&self) -> Result<(), HyprwallError> {
+        let output = self.output().await?;
+
+        if !output.status.success() {
+            return Err(HyprwallError::CommandFailed {
+                command: self.to_string(),
+                status: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Starts the program and leaves it running, for daemons.
+    pub fn spawn(&self) -> Result<(), HyprwallError> {
+        self.command()
+            .spawn()
+            .map(drop)
+            .map_err(|e| self.launch_error(e, "start"))
+    }
+
+    fn launch_error(&self, error: io::Error, action: &str) -> HyprwallError {
+        if error.kind() == io::ErrorKind::NotFound {
+            HyprwallError::BackendNotInstalled {
+                program: self.program.to_string(),
+            }
+        } else {
+            HyprwallError::Other(format!("Failed to {} '{}': {}", action, self, error))
+        }
+    }
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            let arg = arg.to_string_lossy();
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                write!(f, " {:?}", arg)?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{feh, hyprpaper, swaybg, swww, Assignment};
+
+    // Paths a shell would act on or split rather than pass along.
+    const HOSTILE_PATHS: &[&str] = &[
+        "/walls/$(touch pwned).png",
+        "/walls/`touch pwned`.png",
+        "/walls/say \"cheese\".png",
+        "/walls/two  spaces .png",
+        "/walls/a,b,c.png",
+    ];
+
+    // The argv is what the program receives, since nothing is re-parsed on the
+    // way there.
+    fn assert_argv(command: &Cmd, program: &str, expected: &[&str]) {
+        assert_eq!(command.program(), program, "{}", command);
+        assert_eq!(command.args, expected, "{}", command);
+    }
+
+    #[test]
+    fn hostile_paths_reach_backends_unchanged() {
+        let assignments: Vec<Assignment> = HOSTILE_PATHS
+            .iter()
+            .map(|path| Assignment {
+                monitor: Some("DP-1".to_string()),
+                path: path.to_string(),
+            })
+            .collect();
+
+        for path in HOSTILE_PATHS {
+            let target = format!("DP-1,{}", path);
+            assert_argv(
+                &hyprpaper::preload(path),
+                "hyprctl",
+                &["hyprpaper", "preload", path],
+            );
+            assert_argv(
+                &hyprpaper::show("DP-1", path),
+                "hyprctl",
+                &["hyprpaper", "wallpaper", &target],
+            );
+        }
+
+        for assignment in &assignments {
+            assert_argv(
+                &swww::command(assignment),
+                "swww",
+                &["img", "-o", "DP-1", &assignment.path],
+            );
+        }
+
+        let expected: Vec<&str> = HOSTILE_PATHS
+            .iter()
+            .flat_map(|path| ["-o", "DP-1", "-i", path, "-m", "fill"])
+            .collect();
+        assert_argv(&swaybg::command(&assignments), "swaybg", &expected);
+
+        let expected: Vec<&str> = std::iter::once("--bg-fill")
+            .chain(HOSTILE_PATHS.iter().copied())
+            .collect();
+        assert_argv(&feh::command(&assignments), "feh", &expected);
+    }
+
+    #[test]
+    fn display_quotes_arguments_with_whitespace() {
+        let command = Cmd::new("swww").args(["img", "/walls/two  spaces .png", ""]);
+        assert_eq!(
+            command.to_string(),
+            "swww img \"/walls/two  spaces .png\" \"\""
+        );
+    }
+}
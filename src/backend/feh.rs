@@ -0,0 +1,60 @@
+use super::{Assignment, Backend, BoxFuture, Capabilities, Cmd, Requirements, Session};
+use crate::error::HyprwallError;
+use crate::scan::Format;
+
+pub struct Feh;
+
+impl Backend for Feh {
+    fn name(&self) -> &'static str {
+        "feh"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Feh"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: &[
+                Format::Png,
+                Format::Jpeg,
+                Format::WebP,
+                Format::Bmp,
+                Format::Tiff,
+            ],
+            per_monitor: true,
+            positional: true,
+        }
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            programs: &["feh"],
+            session: Session::X11,
+        }
+    }
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>> {
+        Box::pin(async move { command(wallpapers).run().await })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn stop(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+// feh hands out images to Xinerama screens in the order they are given,
+// callers pass assignments in monitor order.
+pub(super) fn command(wallpapers: &[Assignment]) -> Cmd {
+    Cmd::new("feh")
+        .arg("--bg-fill")
+        .args(wallpapers.iter().map(|wallpaper| &wallpaper.path))
+}
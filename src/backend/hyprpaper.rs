@@ -0,0 +1,154 @@
+use super::{
+    is_process_running, kill_process, start_process, Assignment, Backend, BoxFuture, Capabilities,
+    Cmd, Requirements, Session,
+};
+use crate::error::HyprwallError;
+use crate::monitor::get_monitors;
+use crate::scan::Format;
+use std::path::PathBuf;
+
+pub struct Hyprpaper;
+
+impl Backend for Hyprpaper {
+    fn name(&self) -> &'static str {
+        "hyprpaper"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Hyprpaper"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: &[Format::Png, Format::Jpeg, Format::WebP, Format::Jxl],
+            per_monitor: true,
+            positional: false,
+        }
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            programs: &["hyprpaper", "hyprctl"],
+            session: Session::Hyprland,
+        }
+    }
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>> {
+        Box::pin(async {
+            let hyprpaper_config_path = config_path();
+            if ipc_disabled() {
+                return Err(format!(
+                    "IPC is turned off in {}, hyprwall sets wallpapers through it",
+                    hyprpaper_config_path.display()
+                )
+                .into());
+            }
+
+            if !is_process_running("hyprpaper").await {
+                println!("hyprpaper is not running. Attempting to start it...");
+
+                if !hyprpaper_config_path.exists() {
+                    std::fs::create_dir_all(hyprpaper_config_path.parent().unwrap()).map_err(
+                        |e| {
+                            format!(
+                                "Failed to create {}: {}",
+                                hyprpaper_config_path.display(),
+                                e
+                            )
+                        },
+                    )?;
+                    std::fs::File::create(&hyprpaper_config_path).map_err(|e| {
+                        format!(
+                            "Failed to create {}: {}",
+                            hyprpaper_config_path.display(),
+                            e
+                        )
+                    })?;
+                }
+
+                start_process(Cmd::new("hyprpaper")).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>> {
+        Box::pin(async move {
+            let mut preloaded = Vec::new();
+            for wallpaper in wallpapers {
+                if !preloaded.contains(&wallpaper.path) {
+                    preload(&wallpaper.path).run().await?;
+                    preloaded.push(wallpaper.path.clone());
+                }
+            }
+
+            for wallpaper in wallpapers {
+                let monitors = match &wallpaper.monitor {
+                    Some(monitor) => vec![monitor.clone()],
+                    None => get_monitors().await?,
+                };
+
+                for monitor in monitors {
+                    show(&monitor, &wallpaper.path).run().await?;
+                }
+            }
+
+            // Every preload stays in memory until unloaded, which adds up when the
+            // daemon rotates for days. The wallpaper is already shown, so a failed
+            // cleanup doesn't fail the set.
+            if let Err(e) = Cmd::new("hyprctl")
+                .args(["hyprpaper", "unload", "unused"])
+                .run()
+                .await
+            {
+                eprintln!("Warning: failed to unload unused wallpapers: {}", e);
+            }
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {
+            let _ = Cmd::new("hyprctl")
+                .args(["hyprpaper", "unload", "all"])
+                .run()
+                .await;
+        })
+    }
+
+    fn stop(&self) -> BoxFuture<'_, ()> {
+        Box::pin(kill_process("hyprpaper"))
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    crate::config::config_home().join("hypr/hyprpaper.conf")
+}
+
+// hyprpaper listens for hyprctl unless its config says otherwise.
+pub fn ipc_disabled() -> bool {
+    let contents = std::fs::read_to_string(config_path()).unwrap_or_default();
+    contents.lines().any(|line| {
+        line.split_once('=').is_some_and(|(key, value)| {
+            key.trim() == "ipc" && matches!(value.trim(), "off" | "false" | "0" | "no")
+        })
+    })
+}
+
+pub async fn check_ipc() -> Result<(), HyprwallError> {
+    Cmd::new("hyprctl")
+        .args(["hyprpaper", "listloaded"])
+        .run()
+        .await
+}
+
+pub(super) fn preload(path: &str) -> Cmd {
+    Cmd::new("hyprctl").args(["hyprpaper", "preload", path])
+}
+
+// hyprpaper splits at the first comma, so commas in the path are kept.
+pub(super) fn show(monitor: &str, path: &str) -> Cmd {
+    Cmd::new("hyprctl")
+        .args(["hyprpaper", "wallpaper"])
+        .arg(format!("{},{}", monitor, path))
+}
@@ -0,0 +1,106 @@
+mod detect;
+mod exec;
+mod feh;
+pub mod hyprpaper;
+mod swaybg;
+mod swww;
+mod wallutils;
+
+use crate::error::HyprwallError;
+use crate::scan::Format;
+use detect::{Requirements, Session};
+use std::future::Future;
+use std::pin::Pin;
+
+pub use detect::{availability, find_program, pick, Environment};
+pub use exec::Cmd;
+pub use feh::Feh;
+pub use hyprpaper::Hyprpaper;
+pub use swaybg::Swaybg;
+pub use swww::Swww;
+pub use wallutils::Wallutils;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    // Formats the backend reads itself, anything else is converted to PNG first.
+    pub formats: &'static [Format],
+    pub per_monitor: bool,
+    // Outputs are matched to assignments by their order rather than by name,
+    // so every monitor needs one or the later ones shift.
+    pub positional: bool,
+}
+
+impl Capabilities {
+    pub fn supports(&self, format: Format) -> bool {
+        self.formats.contains(&format)
+    }
+}
+
+// A monitor of None targets every output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub monitor: Option<String>,
+    pub path: String,
+}
+
+// Adding a backend means implementing this trait and listing it in BACKENDS,
+// the CLI, config and GUI all read from that registry.
+pub trait Backend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    fn requirements(&self) -> Requirements;
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>>;
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>>;
+
+    fn clear(&self) -> BoxFuture<'_, ()>;
+
+    fn stop(&self) -> BoxFuture<'_, ()>;
+}
+
+// Listed in order of preference among backends for the same session.
+pub static BACKENDS: &[&dyn Backend] = &[&Hyprpaper, &Swww, &Swaybg, &Feh, &Wallutils];
+
+pub fn find(name: &str) -> Option<&'static dyn Backend> {
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|backend| backend.name().eq_ignore_ascii_case(name.trim()))
+}
+
+pub fn same(a: &dyn Backend, b: &dyn Backend) -> bool {
+    a.name() == b.name()
+}
+
+pub async fn is_process_running(process_name: &str) -> bool {
+    Cmd::new("pgrep")
+        .args(["-x", process_name])
+        .run()
+        .await
+        .is_ok()
+}
+
+async fn start_process(command: Cmd) -> Result<(), HyprwallError> {
+    command.spawn()?;
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    if is_process_running(command.program()).await {
+        Ok(())
+    } else {
+        Err(HyprwallError::BackendFailedToStart {
+            program: command.program().to_string(),
+        })
+    }
+}
+
+async fn kill_process(process_name: &str) {
+    let _ = Cmd::new("killall").arg(process_name).quiet().run().await;
+}
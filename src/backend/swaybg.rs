@@ -0,0 +1,77 @@
+use super::{
+    is_process_running, kill_process, Assignment, Backend, BoxFuture, Capabilities, Cmd,
+    Requirements, Session,
+};
+use crate::error::HyprwallError;
+use crate::scan::Format;
+
+pub struct Swaybg;
+
+impl Backend for Swaybg {
+    fn name(&self) -> &'static str {
+        "swaybg"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Swaybg"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: &[Format::Png, Format::Jpeg, Format::Bmp, Format::Tiff],
+            per_monitor: true,
+            positional: false,
+        }
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            programs: &["swaybg"],
+            session: Session::Wayland,
+        }
+    }
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>> {
+        // swaybg is spawned with its images by set
+        Box::pin(async { Ok(()) })
+    }
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>> {
+        Box::pin(async move {
+            // A single swaybg instance draws every output, so replace it as a whole.
+            kill_process("swaybg").await;
+
+            command(wallpapers).spawn()?;
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            if is_process_running("swaybg").await {
+                Ok(())
+            } else {
+                Err(HyprwallError::BackendFailedToStart {
+                    program: "swaybg".to_string(),
+                })
+            }
+        })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn stop(&self) -> BoxFuture<'_, ()> {
+        Box::pin(kill_process("swaybg"))
+    }
+}
+
+pub(super) fn command(wallpapers: &[Assignment]) -> Cmd {
+    wallpapers
+        .iter()
+        .fold(Cmd::new("swaybg"), |command, wallpaper| {
+            command
+                .arg("-o")
+                .arg(wallpaper.monitor.as_deref().unwrap_or("*"))
+                .arg("-i")
+                .arg(&wallpaper.path)
+                .args(["-m", "fill"])
+        })
+}
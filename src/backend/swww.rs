@@ -0,0 +1,77 @@
+use super::{
+    is_process_running, kill_process, start_process, Assignment, Backend, BoxFuture, Capabilities,
+    Cmd, Requirements, Session,
+};
+use crate::error::HyprwallError;
+use crate::scan::Format;
+
+pub struct Swww;
+
+impl Backend for Swww {
+    fn name(&self) -> &'static str {
+        "swww"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Swww"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: &[
+                Format::Png,
+                Format::Jpeg,
+                Format::Gif,
+                Format::WebP,
+                Format::Bmp,
+                Format::Tiff,
+            ],
+            per_monitor: true,
+            positional: false,
+        }
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            programs: &["swww", "swww-daemon"],
+            session: Session::Wayland,
+        }
+    }
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>> {
+        Box::pin(async {
+            if !is_process_running("swww-daemon").await {
+                println!("swww is not running. Attempting to start it...");
+                start_process(Cmd::new("swww-daemon").quiet()).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>> {
+        Box::pin(async move {
+            for wallpaper in wallpapers {
+                command(wallpaper).run().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {
+            let _ = Cmd::new("swww").arg("clear").run().await;
+        })
+    }
+
+    fn stop(&self) -> BoxFuture<'_, ()> {
+        Box::pin(kill_process("swww-daemon"))
+    }
+}
+
+pub(super) fn command(wallpaper: &Assignment) -> Cmd {
+    let command = match &wallpaper.monitor {
+        Some(monitor) => Cmd::new("swww").args(["img", "-o", monitor.as_str()]),
+        None => Cmd::new("swww").arg("img"),
+    };
+    command.arg(&wallpaper.path)
+}
@@ -0,0 +1,51 @@
+use super::{Assignment, Backend, BoxFuture, Capabilities, Cmd, Requirements, Session};
+use crate::error::HyprwallError;
+use crate::scan::Format;
+
+pub struct Wallutils;
+
+impl Backend for Wallutils {
+    fn name(&self) -> &'static str {
+        "wallutils"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Wallutils"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: &[Format::Png, Format::Jpeg],
+            per_monitor: false,
+            positional: false,
+        }
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            programs: &["setwallpaper"],
+            session: Session::Any,
+        }
+    }
+
+    fn start(&self) -> BoxFuture<'_, Result<(), HyprwallError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn set<'a>(&'a self, wallpapers: &'a [Assignment]) -> BoxFuture<'a, Result<(), HyprwallError>> {
+        Box::pin(async move {
+            let wallpaper = wallpapers
+                .first()
+                .ok_or(HyprwallError::Other("No wallpaper given".to_string()))?;
+            Cmd::new("setwallpaper").arg(&wallpaper.path).run().await
+        })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn stop(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
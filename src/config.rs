@@ -0,0 +1,960 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::backend::{self, Backend};
+use crate::scan;
+use crate::sort;
+
+const CONFIG_FILE: &str = "hyprwall/config.ini";
+const SETTINGS: &str = "Settings";
+const PROFILE: &str = "Profile.";
+const SOURCES: &str = "Sources";
+
+const DEFAULT_TEXTURE_CACHE_MB: usize = 256;
+
+pub const DEFAULT_CONFIG: &str = r#"[Settings]
+folder = none
+backend = none
+last_wallpaper = none
+default_wallpaper = none
+"#;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Invalid {
+        line: usize,
+        key: Option<String>,
+        message: String,
+    },
+}
+
+impl ConfigError {
+    pub fn invalid(line: usize, key: Option<&str>, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            line,
+            key: key.map(String::from),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(message) => write!(f, "{}", message),
+            ConfigError::Invalid {
+                line,
+                key: Some(key),
+                message,
+            } => write!(f, "line {}, key '{}': {}", line, key, message),
+            ConfigError::Invalid {
+                line,
+                key: None,
+                message,
+            } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+// Comments, blank lines and untouched entries are written back exactly as
+// they were read.
+#[derive(Clone, Debug, PartialEq)]
+enum Line {
+    Raw(String),
+    Section {
+        name: String,
+        raw: String,
+    },
+    Entry {
+        key: String,
+        value: String,
+        number: usize,
+        raw: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    lines: Vec<Line>,
+}
+
+// A wallpaper folder, either the [Settings] folder or a `name = path` entry
+// of [Sources].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    Off,
+    Every(Duration),
+}
+
+impl Rotation {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "off" | "none" => Ok(Rotation::Off),
+            value => crate::daemon::parse_interval(value).map(Rotation::Every),
+        }
+    }
+}
+
+// A named [Profile.<name>] section. Keys it leaves out keep their current
+// value when the profile is used.
+pub struct Profile {
+    pub name: String,
+    pub folder: Option<PathBuf>,
+    pub backend: Option<&'static dyn Backend>,
+    pub wallpaper: Option<String>,
+    pub monitors: BTreeMap<String, String>,
+    pub rotation: Option<Rotation>,
+}
+
+impl Profile {
+    pub fn sets_wallpaper(&self) -> bool {
+        self.wallpaper.is_some() || !self.monitors.is_empty()
+    }
+}
+
+static OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+// Set from --config before anything reads the config.
+pub fn set_override(path: PathBuf) {
+    let _ = OVERRIDE.set(path);
+}
+
+pub fn path() -> PathBuf {
+    resolve_path(
+        OVERRIDE.get(),
+        std::env::var_os("HYPRWALL_CONFIG"),
+        std::env::var_os("XDG_CONFIG_HOME"),
+    )
+}
+
+// --config wins over HYPRWALL_CONFIG, which wins over the XDG location.
+fn resolve_path(
+    flag: Option<&PathBuf>,
+    variable: Option<OsString>,
+    config_home: Option<OsString>,
+) -> PathBuf {
+    if let Some(path) = flag {
+        return path.clone();
+    }
+    match variable.filter(|path| !path.is_empty()) {
+        Some(path) => absolute_path(PathBuf::from(path)),
+        None => base_dir(config_home, "~/.config").join(CONFIG_FILE),
+    }
+}
+
+// Relative paths are taken from the working directory, so they still mean the
+// same file to a daemon started somewhere else.
+pub fn absolute_path(path: PathBuf) -> PathBuf {
+    let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).into_owned());
+    if path.is_relative() {
+        std::env::current_dir()
+            .map(|cur| cur.join(&path))
+            .unwrap_or(path)
+    } else {
+        path
+    }
+}
+
+pub fn config_home() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", "~/.config")
+}
+
+pub fn cache_home() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", "~/.cache")
+}
+
+pub fn cache_dir() -> PathBuf {
+    cache_home().join("hyprwall")
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    base_dir(std::env::var_os(var), fallback)
+}
+
+// The base-dir spec says relative values are invalid and must be ignored.
+fn base_dir(value: Option<OsString>, fallback: &str) -> PathBuf {
+    value
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| PathBuf::from(shellexpand::tilde(fallback).into_owned()))
+}
+
+// For readers that can carry on with defaults, problems are reported but not fatal.
+pub fn load() -> Config {
+    Config::open(&path()).unwrap_or_else(|e| {
+        eprintln!("Error reading config file: {}", e);
+        Config::default()
+    })
+}
+
+// Writes are skipped when the file can't be parsed so user edits aren't lost.
+// The lock is held from read to rename so concurrent updates from the GUI,
+// the CLI and the daemon don't drop each other's keys.
+pub fn update(f: impl FnOnce(&mut Config)) {
+    if let Err(e) = update_at(&path(), f) {
+        eprintln!("Not saving config file: {}", e);
+    }
+}
+
+pub fn update_at(path: &Path, f: impl FnOnce(&mut Config)) -> Result<(), ConfigError> {
+    let _lock = lock(path)
+        .map_err(|e| ConfigError::Io(format!("Failed to lock {}: {}", path.display(), e)))?;
+    let mut config = Config::open(path)?;
+    f(&mut config);
+    write_atomic(path, &config.to_string())
+        .map_err(|e| ConfigError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+pub fn write_default() -> std::io::Result<()> {
+    let path = path();
+    let _lock = lock(&path)?;
+    write_atomic(&path, DEFAULT_CONFIG)
+}
+
+// The config file itself is replaced on every write, so the lock lives on a
+// sibling file whose inode stays put. Released when the handle is dropped.
+pub fn lock(path: &Path) -> std::io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, "lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+// Readers see either the old or the new file, never a truncated one. A
+// symlinked config (dotfile managers) is written through, not replaced.
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let path = &std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = sibling(path, &format!("{}.tmp", std::process::id()));
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+        .into_owned()
+}
+
+pub fn with_tilde(path: &str) -> String {
+    tilde_under(path, &std::env::var("HOME").unwrap_or_default())
+}
+
+// Only a leading home folder is shortened. An empty or root HOME would match
+// everything, so it leaves the path alone.
+fn tilde_under(path: &str, home: &str) -> String {
+    let home = home.trim_end_matches('/');
+    match path.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+            format!("~{}", rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+impl Config {
+    // A missing file reads as an empty config.
+    pub fn open(path: &Path) -> Result<Self, ConfigError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(ConfigError::Io(format!(
+                    "Failed to read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        let config = Self::parse(&contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut lines = Vec::new();
+
+        for (index, raw) in contents.lines().enumerate() {
+            let number = index + 1;
+            let line = raw.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                lines.push(Line::Raw(raw.to_string()));
+            } else if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or_else(|| {
+                    ConfigError::invalid(number, None, "section header is missing ']'")
+                })?;
+                lines.push(Line::Section {
+                    name: name.trim().to_string(),
+                    raw: raw.to_string(),
+                });
+            } else {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| ConfigError::invalid(number, None, "expected `key = value`"))?;
+                let key = key.trim();
+                if key.is_empty() {
+                    return Err(ConfigError::invalid(number, None, "missing key before '='"));
+                }
+                lines.push(Line::Entry {
+                    key: key.to_string(),
+                    value: value.trim().to_string(),
+                    number,
+                    raw: Some(raw.to_string()),
+                });
+            }
+        }
+
+        Ok(Self { lines })
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let sections = std::iter::once(SETTINGS.to_string()).chain(
+            self.profiles()
+                .into_iter()
+                .map(|name| format!("{}{}", PROFILE, name)),
+        );
+
+        for section in sections {
+            for (number, key, value) in self.section(&section) {
+                match key {
+                    "backend" if value != "none" && backend::find(value).is_none() => {
+                        let known: Vec<&str> = backend::BACKENDS.iter().map(|b| b.name()).collect();
+                        return Err(ConfigError::invalid(
+                            number,
+                            Some(key),
+                            format!(
+                                "unknown backend '{}', expected none or one of {}",
+                                value,
+                                known.join(", ")
+                            ),
+                        ));
+                    }
+                    "recursive" if section == SETTINGS && value.parse::<bool>().is_err() => {
+                        return Err(ConfigError::invalid(
+                            number,
+                            Some(key),
+                            "expected true or false",
+                        ));
+                    }
+                    "max_depth" if section == SETTINGS && value.parse::<usize>().is_err() => {
+                        return Err(ConfigError::invalid(
+                            number,
+                            Some(key),
+                            "expected a number of folder levels",
+                        ));
+                    }
+                    "sort" if section == SETTINGS => {
+                        sort::SortKey::parse(value)
+                            .map_err(|e| ConfigError::invalid(number, Some(key), e))?;
+                    }
+                    "texture_cache" if section == SETTINGS && value.parse::<usize>().is_err() => {
+                        return Err(ConfigError::invalid(
+                            number,
+                            Some(key),
+                            "expected a size in megabytes",
+                        ));
+                    }
+                    "interval" if section != SETTINGS => {
+                        Rotation::parse(value)
+                            .map_err(|e| ConfigError::invalid(number, Some(key), e))?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Entries before the first header count as [Settings], matching older configs.
+    fn sections(&self) -> impl Iterator<Item = (&str, &Line)> {
+        let mut current = SETTINGS;
+        self.lines.iter().map(move |line| {
+            if let Line::Section { name, .. } = line {
+                current = name.as_str();
+            }
+            (current, line)
+        })
+    }
+
+    pub fn section(&self, name: &str) -> Vec<(usize, &str, &str)> {
+        self.sections()
+            .filter(|(section, _)| section.eq_ignore_ascii_case(name))
+            .filter_map(|(_, line)| match line {
+                Line::Entry {
+                    key, value, number, ..
+                } => Some((*number, key.as_str(), value.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .into_iter()
+            .find(|(_, k, _)| *k == key)
+            .map(|(_, _, value)| value)
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let mut existing = None;
+        let mut insert_at = None;
+
+        for (index, (current, line)) in self.sections().enumerate() {
+            if !current.eq_ignore_ascii_case(section) {
+                continue;
+            }
+            match line {
+                Line::Entry { key: k, .. } if k == key => {
+                    existing = Some(index);
+                    break;
+                }
+                Line::Entry { .. } | Line::Section { .. } => insert_at = Some(index + 1),
+                Line::Raw(_) => {}
+            }
+        }
+
+        if let Some(index) = existing {
+            if let Line::Entry { value: v, raw, .. } = &mut self.lines[index] {
+                *v = value.to_string();
+                *raw = None;
+            }
+            return;
+        }
+
+        let entry = Line::Entry {
+            key: key.to_string(),
+            value: value.to_string(),
+            number: 0,
+            raw: None,
+        };
+
+        match insert_at {
+            Some(index) => self.lines.insert(index, entry),
+            None => {
+                if !matches!(self.lines.last(), None | Some(Line::Raw(_))) {
+                    self.lines.push(Line::Raw(String::new()));
+                }
+                self.lines.push(Line::Section {
+                    name: section.to_string(),
+                    raw: format!("[{}]", section),
+                });
+                self.lines.push(entry);
+            }
+        }
+    }
+
+    pub fn retain(&mut self, section: &str, f: impl Fn(&str) -> bool) {
+        let keep: Vec<bool> = self
+            .sections()
+            .map(|(current, line)| match line {
+                Line::Entry { key, .. } => {
+                    !current.eq_ignore_ascii_case(section) || f(key.as_str())
+                }
+                _ => true,
+            })
+            .collect();
+        let mut keep = keep.into_iter();
+        self.lines.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    fn setting(&self, key: &str) -> Option<&str> {
+        self.get(SETTINGS, key).filter(|value| *value != "none")
+    }
+
+    pub fn folder(&self) -> Option<PathBuf> {
+        self.setting("folder")
+            .map(|folder| PathBuf::from(shellexpand::tilde(folder).into_owned()))
+    }
+
+    pub fn set_folder(&mut self, folder: &Path) {
+        self.set(SETTINGS, "folder", &with_tilde(&folder.to_string_lossy()));
+    }
+
+    pub fn sources(&self) -> Vec<Source> {
+        let mut sources: Vec<Source> = Vec::new();
+        let folder = self.folder().map(|path| Source {
+            name: folder_name(&path),
+            path,
+        });
+        let extra = self
+            .section(SOURCES)
+            .into_iter()
+            .map(|(_, name, path)| Source {
+                name: name.to_string(),
+                path: PathBuf::from(shellexpand::tilde(path).into_owned()),
+            });
+
+        for source in folder.into_iter().chain(extra) {
+            if !sources.iter().any(|s| s.path == source.path) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
+
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        self.sources()
+            .into_iter()
+            .map(|source| source.path)
+            .collect()
+    }
+
+    // The first folder becomes the [Settings] folder, later ones go to
+    // [Sources] under a name that isn't taken yet. Returns the name used.
+    pub fn add_source(&mut self, path: &Path, name: Option<&str>) -> String {
+        let sources = self.sources();
+        if let Some(existing) = sources.iter().find(|s| s.path == path) {
+            return existing.name.clone();
+        }
+        if self.folder().is_none() {
+            self.set_folder(path);
+            return folder_name(path);
+        }
+
+        let base = name.map_or_else(|| folder_name(path), String::from);
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while sources.iter().any(|s| s.name == name) {
+            name = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        self.set(SOURCES, &name, &with_tilde(&path.to_string_lossy()));
+        name
+    }
+
+    // Removes the source with the given name or path. Taking away the
+    // [Settings] folder promotes the next source in its place.
+    pub fn remove_source(&mut self, source: &str) -> Option<Source> {
+        let path = PathBuf::from(shellexpand::tilde(source).into_owned());
+        let removed = self
+            .sources()
+            .into_iter()
+            .find(|s| s.name == source || s.path == path)?;
+
+        if self.folder().as_ref() == Some(&removed.path) {
+            let next = self.section(SOURCES).first().map(|(_, name, path)| {
+                (
+                    name.to_string(),
+                    PathBuf::from(shellexpand::tilde(path).into_owned()),
+                )
+            });
+            match next {
+                Some((name, path)) => {
+                    self.set_folder(&path);
+                    self.retain(SOURCES, |key| key != name);
+                }
+                None => self.set(SETTINGS, "folder", "none"),
+            }
+        } else {
+            let removed_path = removed.path.clone();
+            let keys: Vec<String> = self
+                .section(SOURCES)
+                .into_iter()
+                .filter(|(_, _, path)| Path::new(shellexpand::tilde(path).as_ref()) == removed_path)
+                .map(|(_, key, _)| key.to_string())
+                .collect();
+            self.retain(SOURCES, |key| !keys.iter().any(|k| k == key));
+        }
+        Some(removed)
+    }
+
+    pub fn scan_options(&self) -> scan::Options {
+        let defaults = scan::Options::default();
+        scan::Options {
+            recursive: self
+                .setting("recursive")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.recursive),
+            max_depth: self
+                .setting("max_depth")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.max_depth),
+        }
+    }
+
+    // Budget for decoded thumbnails and previews in the GUI, in bytes.
+    pub fn texture_cache_size(&self) -> usize {
+        self.setting("texture_cache")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_TEXTURE_CACHE_MB)
+            .saturating_mul(1024 * 1024)
+    }
+
+    pub fn backend(&self) -> Option<&'static dyn Backend> {
+        self.setting("backend").and_then(backend::find)
+    }
+
+    pub fn set_backend(&mut self, backend: Option<&dyn Backend>) {
+        self.set(SETTINGS, "backend", backend.map_or("none", |b| b.name()));
+    }
+
+    pub fn last_wallpaper(&self) -> Option<String> {
+        self.setting("last_wallpaper").map(String::from)
+    }
+
+    pub fn set_last_wallpaper(&mut self, path: &str) {
+        self.set(SETTINGS, "last_wallpaper", &with_tilde(path));
+    }
+
+    pub fn sort(&self) -> sort::SortKey {
+        self.setting("sort")
+            .and_then(|value| sort::SortKey::parse(value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn set_sort(&mut self, key: sort::SortKey) {
+        self.set(SETTINGS, "sort", key.name());
+    }
+
+    pub fn default_wallpaper(&self) -> Option<String> {
+        self.setting("default_wallpaper").map(String::from)
+    }
+
+    pub fn monitor_wallpapers(&self) -> BTreeMap<String, String> {
+        self.section(SETTINGS)
+            .into_iter()
+            .filter_map(|(_, key, value)| {
+                let monitor = key.strip_prefix("monitor.")?;
+                Some((monitor.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+
+    pub fn set_monitor_wallpaper(&mut self, monitor: &str, path: &str) {
+        self.set(SETTINGS, &format!("monitor.{}", monitor), &with_tilde(path));
+    }
+
+    pub fn clear_monitor_wallpapers(&mut self) {
+        self.retain(SETTINGS, |key| !key.starts_with("monitor."));
+    }
+
+    pub fn profiles(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for line in &self.lines {
+            if let Line::Section { name, .. } = line {
+                if let Some(profile) = name.strip_prefix(PROFILE) {
+                    if !names.iter().any(|n: &String| n == profile) {
+                        names.push(profile.to_string());
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        if !self.profiles().iter().any(|n| n == name) {
+            return None;
+        }
+
+        let mut profile = Profile {
+            name: name.to_string(),
+            folder: None,
+            backend: None,
+            wallpaper: None,
+            monitors: BTreeMap::new(),
+            rotation: None,
+        };
+
+        for (_, key, value) in self.section(&format!("{}{}", PROFILE, name)) {
+            match key {
+                "folder" => {
+                    profile.folder = Some(PathBuf::from(shellexpand::tilde(value).into_owned()))
+                }
+                "backend" => profile.backend = backend::find(value),
+                "wallpaper" => profile.wallpaper = Some(value.to_string()),
+                "interval" => profile.rotation = Rotation::parse(value).ok(),
+                key => {
+                    if let Some(monitor) = key.strip_prefix("monitor.") {
+                        profile
+                            .monitors
+                            .insert(monitor.to_string(), value.to_string());
+                    }
+                }
+            }
+        }
+
+        Some(profile)
+    }
+
+    pub fn active_profile(&self) -> Option<Profile> {
+        self.setting("profile").and_then(|name| self.profile(name))
+    }
+
+    // Copies the profile over [Settings] so everything that reads the config
+    // directly (restore, random, the GUI) follows the switch.
+    pub fn use_profile(&mut self, profile: &Profile) {
+        self.set(SETTINGS, "profile", &profile.name);
+        if let Some(folder) = &profile.folder {
+            self.set_folder(folder);
+        }
+        if let Some(backend) = profile.backend {
+            self.set_backend(Some(backend));
+        }
+        if profile.sets_wallpaper() {
+            self.clear_monitor_wallpapers();
+        }
+        if let Some(wallpaper) = &profile.wallpaper {
+            self.set_last_wallpaper(wallpaper);
+        }
+        for (monitor, path) in &profile.monitors {
+            self.set_monitor_wallpaper(monitor, path);
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Raw(raw) | Line::Section { raw, .. } => writeln!(f, "{}", raw)?,
+                Line::Entry { raw: Some(raw), .. } => writeln!(f, "{}", raw)?,
+                Line::Entry { key, value, .. } => writeln!(f, "{} = {}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const HAND_WRITTEN: &str = concat!(
+        "# hyprwall config\n",
+        "[Settings]\n",
+        "folder=~/Pictures\n",
+        "  backend   =   swww  \n",
+        "; picked by hand\n",
+        "unknown_key = kept as is\n",
+        "\n",
+        "[Custom]\n",
+        "anything = goes\n",
+    );
+
+    #[test]
+    fn untouched_lines_round_trip() {
+        let config = Config::parse(HAND_WRITTEN).unwrap();
+        assert_eq!(config.to_string(), HAND_WRITTEN);
+    }
+
+    #[test]
+    fn set_only_rewrites_its_own_line() {
+        let mut config = Config::parse(HAND_WRITTEN).unwrap();
+        config.set(SETTINGS, "backend", "feh");
+        config.set(SETTINGS, "sort", "size");
+
+        let expected = HAND_WRITTEN
+            .replace("  backend   =   swww  \n", "backend = feh\n")
+            .replace(
+                "unknown_key = kept as is\n",
+                "unknown_key = kept as is\nsort = size\n",
+            );
+        assert_eq!(config.to_string(), expected);
+        assert_eq!(config.get(SETTINGS, "backend"), Some("feh"));
+        assert_eq!(config.get("Custom", "anything"), Some("goes"));
+    }
+
+    #[test]
+    fn set_adds_missing_sections() {
+        let mut config = Config::parse("[Settings]\nfolder = ~/a\n").unwrap();
+        config.set("Sources", "b", "~/b");
+        assert_eq!(
+            config.to_string(),
+            "[Settings]\nfolder = ~/a\n\n[Sources]\nb = ~/b\n"
+        );
+    }
+
+    #[test]
+    fn entries_before_a_header_are_settings() {
+        let config = Config::parse("folder = ~/a\n").unwrap();
+        assert_eq!(config.get(SETTINGS, "folder"), Some("~/a"));
+    }
+
+    #[test]
+    fn syntax_errors_name_the_line() {
+        let error = Config::parse("[Settings]\nfolder ~/a\n").unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::invalid(2, None, "expected `key = value`")
+        );
+        assert_eq!(error.to_string(), "line 2: expected `key = value`");
+
+        let error = Config::parse("[Settings\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: section header is missing ']'");
+    }
+
+    #[test]
+    fn validation_errors_name_the_line_and_key() {
+        let config = Config::parse("[Settings]\n# note\nrecursive = maybe\n").unwrap();
+        let error = config.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3, key 'recursive': expected true or false"
+        );
+
+        let config = Config::parse("[Profile.work]\ninterval = soon\n").unwrap();
+        let Err(ConfigError::Invalid { line, key, .. }) = config.validate() else {
+            panic!("expected an invalid interval");
+        };
+        assert_eq!((line, key.as_deref()), (2, Some("interval")));
+    }
+
+    #[test]
+    fn concurrent_updates_keep_every_key() {
+        const WRITERS: usize = 16;
+        const ROUNDS: usize = 10;
+        let dir = TempDir::new("config-update");
+        let path = dir.write("config.ini", "# shared\n[Settings]\nfolder = ~/walls\n");
+
+        std::thread::scope(|scope| {
+            for writer in 0..WRITERS {
+                let path = &path;
+                scope.spawn(move || {
+                    for round in 0..ROUNDS {
+                        update_at(path, |config| {
+                            config.set("Test", &format!("writer{}", writer), &round.to_string())
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        let config = Config::open(&path).unwrap();
+        assert_eq!(config.get(SETTINGS, "folder"), Some("~/walls"));
+        for writer in 0..WRITERS {
+            let key = format!("writer{}", writer);
+            assert_eq!(
+                config.get("Test", &key),
+                Some((ROUNDS - 1).to_string().as_str()),
+                "{} was lost",
+                key
+            );
+        }
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# shared\n"));
+    }
+
+    #[test]
+    fn open_reads_the_given_path() {
+        let dir = TempDir::new("config-open");
+        let path = dir.write("config.ini", "[Settings]\nfolder = ~/walls\n");
+        let config = Config::open(&path).unwrap();
+        assert_eq!(config.get(SETTINGS, "folder"), Some("~/walls"));
+
+        let missing = Config::open(&dir.join("missing.ini")).unwrap();
+        assert_eq!(missing.to_string(), "");
+
+        dir.write("bad.ini", "[Settings]\nmax_depth = deep\n");
+        assert!(matches!(
+            Config::open(&dir.join("bad.ini")),
+            Err(ConfigError::Invalid { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn only_a_leading_home_becomes_a_tilde() {
+        assert_eq!(
+            tilde_under("/home/ada/walls/a.png", "/home/ada"),
+            "~/walls/a.png"
+        );
+        assert_eq!(tilde_under("/home/ada", "/home/ada/"), "~");
+        assert_eq!(
+            tilde_under("/home/adam/a.png", "/home/ada"),
+            "/home/adam/a.png"
+        );
+        assert_eq!(
+            tilde_under("/mnt/home/ada/a.png", "/home/ada"),
+            "/mnt/home/ada/a.png"
+        );
+    }
+
+    #[test]
+    fn an_empty_home_leaves_paths_alone() {
+        assert_eq!(tilde_under("/walls/a.png", ""), "/walls/a.png");
+        assert_eq!(tilde_under("/walls/a.png", "/"), "/walls/a.png");
+    }
+
+    #[test]
+    fn config_path_precedence() {
+        let flag = PathBuf::from("/etc/flag.ini");
+        let variable = || Some(OsString::from("/etc/variable.ini"));
+        let config_home = || Some(OsString::from("/xdg"));
+        let home_config = PathBuf::from(shellexpand::tilde("~/.config").into_owned());
+
+        assert_eq!(resolve_path(Some(&flag), variable(), config_home()), flag);
+        assert_eq!(
+            resolve_path(None, variable(), config_home()),
+            Path::new("/etc/variable.ini")
+        );
+        assert_eq!(
+            resolve_path(None, Some(OsString::new()), config_home()),
+            Path::new("/xdg/hyprwall/config.ini")
+        );
+        assert_eq!(
+            resolve_path(None, None, config_home()),
+            Path::new("/xdg/hyprwall/config.ini")
+        );
+        assert_eq!(
+            resolve_path(None, None, None),
+            home_config.join(CONFIG_FILE)
+        );
+        assert_eq!(
+            resolve_path(None, None, Some(OsString::from("relative"))),
+            home_config.join(CONFIG_FILE)
+        );
+    }
+
+    #[test]
+    fn relative_config_variables_are_made_absolute() {
+        assert_eq!(
+            resolve_path(None, Some(OsString::from("my.ini")), None),
+            std::env::current_dir().unwrap().join("my.ini")
+        );
+        assert_eq!(
+            resolve_path(None, Some(OsString::from("~/my.ini")), None),
+            Path::new(&shellexpand::tilde("~/my.ini").into_owned())
+        );
+    }
+}
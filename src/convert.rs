@@ -0,0 +1,96 @@
+use crate::backend::{Assignment, Backend};
+use crate::config;
+use crate::error::HyprwallError;
+use crate::scan::{self, Format};
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::glib;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Vector wallpapers are rendered to fit this box
+const SVG_WIDTH: i32 = 3840;
+const SVG_HEIGHT: i32 = 2160;
+
+// Swaps each wallpaper the backend can't read for a PNG copy in the cache.
+// Files that aren't recognised are passed through for the backend to judge.
+pub async fn for_backend(
+    backend: &dyn Backend,
+    wallpapers: &[Assignment],
+) -> Result<Vec<Assignment>, HyprwallError> {
+    let capabilities = backend.capabilities();
+    let mut converted = Vec::with_capacity(wallpapers.len());
+
+    for wallpaper in wallpapers {
+        let path = PathBuf::from(&wallpaper.path);
+        let path = match scan::sniff(&path) {
+            Some(format) if !capabilities.supports(format) => {
+                tokio::task::spawn_blocking(move || to_png(&path, format))
+                    .await
+                    .map_err(|e| format!("Failed to convert {}: {}", wallpaper.path, e))??
+            }
+            _ => path,
+        };
+        converted.push(Assignment {
+            monitor: wallpaper.monitor.clone(),
+            path: path.to_string_lossy().into_owned(),
+        });
+    }
+
+    Ok(converted)
+}
+
+fn to_png(path: &Path, format: Format) -> Result<PathBuf, HyprwallError> {
+    let target = cached_path(path)?;
+    if target.exists() {
+        // Pruning goes by modification time, so a copy in use must look fresh
+        let _ = std::fs::File::options()
+            .write(true)
+            .open(&target)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        return Ok(target);
+    }
+
+    let pixbuf = match format {
+        Format::Svg => Pixbuf::from_file_at_scale(path, SVG_WIDTH, SVG_HEIGHT, true),
+        _ => Pixbuf::from_file(path),
+    }
+    .map_err(|e| HyprwallError::ImageUnreadable {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    // Written aside first so a half-saved file is never taken for a cached copy
+    let temp = target.with_extension("png.tmp");
+    pixbuf
+        .savev(&temp, "png", &[])
+        .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, &target)
+        .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+
+    println!("Converted {} to {}", path.display(), target.display());
+    Ok(target)
+}
+
+// Keyed on path, size and modification time so an edited image is converted again.
+fn cached_path(path: &Path) -> Result<PathBuf, String> {
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+
+    let key = format!("{}:{}:{}", path.display(), metadata.len(), modified);
+    let hash = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, &key)
+        .ok_or_else(|| format!("Failed to hash {}", path.display()))?;
+
+    Ok(config::cache_dir()
+        .join("converted")
+        .join(format!("{}.png", hash)))
+}
@@ -0,0 +1,465 @@
+use crate::config::{self, Config, Rotation};
+use crate::error::HyprwallError;
+use crate::ipc::{self, Request};
+use crate::schedule::{self, Schedule};
+use crate::watch;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+const HISTORY_SIZE: usize = 50;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+// Longer than anyone rotates, and far from overflowing an Instant.
+const MAX_INTERVAL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+struct Slideshow {
+    requested_interval: Option<Duration>,
+    interval: Option<Duration>,
+    monitor: Option<String>,
+    schedule: Option<Schedule>,
+    queue: Vec<String>,
+    history: Vec<String>,
+    current: Option<String>,
+    paused: bool,
+    watcher: Option<watch::Watcher>,
+}
+
+impl Slideshow {
+    fn new(requested_interval: Option<Duration>, monitor: Option<String>) -> Self {
+        Self {
+            requested_interval,
+            interval: rotation_interval(requested_interval, &config::load()),
+            monitor,
+            schedule: load_schedule(),
+            queue: Vec::new(),
+            history: Vec::new(),
+            current: None,
+            paused: false,
+            watcher: None,
+        }
+    }
+
+    fn pool_sources(&self) -> Vec<PathBuf> {
+        match self.active_entry() {
+            Some(entry) => {
+                let path = PathBuf::from(shellexpand::tilde(&entry.target).into_owned());
+                if path.is_dir() {
+                    vec![path]
+                } else {
+                    Vec::new()
+                }
+            }
+            None => config::load().source_paths(),
+        }
+    }
+
+    // Follows whatever the pool is drawn from right now, so call it again
+    // whenever the schedule or the config changes.
+    fn watch(&mut self, sender: &mpsc::UnboundedSender<watch::Changes>) {
+        drop(self.watcher.take());
+        let sender = sender.clone();
+        self.watcher = watch::watch(
+            self.pool_sources(),
+            crate::wallpaper_formats(),
+            config::load().scan_options(),
+            move |changes| {
+                let _ = sender.send(changes);
+            },
+        )
+        .map_err(|e| eprintln!("Not watching wallpaper folders: {}", e))
+        .ok();
+    }
+
+    fn sync(&mut self, changes: watch::Changes) {
+        let removed: Vec<String> = changes
+            .removed
+            .iter()
+            .map(|path| config::with_tilde(&path.to_string_lossy()))
+            .collect();
+        self.queue.retain(|path| !removed.contains(path));
+        self.history.retain(|path| !removed.contains(path));
+
+        // An empty queue is refilled from a fresh scan anyway
+        if self.queue.is_empty() {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for path in changes.added {
+            let index = rng.gen_range(0..=self.queue.len());
+            self.queue
+                .insert(index, config::with_tilde(&path.to_string_lossy()));
+        }
+    }
+
+    async fn next_wallpaper(&mut self) -> Result<String, HyprwallError> {
+        if self.queue.is_empty() {
+            let mut wallpapers = self.pool().await?;
+            wallpapers.shuffle(&mut rand::thread_rng());
+
+            // Don't repeat the last image of the previous round right away
+            let len = wallpapers.len();
+            if len > 1 && wallpapers.last() == self.current.as_ref() {
+                wallpapers.swap(0, len - 1);
+            }
+
+            self.queue = wallpapers;
+        }
+
+        self.queue.pop().ok_or_else(|| "No wallpapers found".into())
+    }
+
+    async fn pool(&self) -> Result<Vec<String>, HyprwallError> {
+        match self.active_entry() {
+            Some(entry) => schedule::wallpapers(&entry.target).await,
+            None => crate::get_wallpapers().await,
+        }
+    }
+
+    fn active_entry(&self) -> Option<&schedule::Entry> {
+        self.schedule.as_ref()?.active_at(schedule::now())
+    }
+
+    fn until_schedule_change(&self) -> Option<Duration> {
+        self.schedule.as_ref()?.until_next_change(schedule::now())
+    }
+
+    async fn advance(&mut self) -> Result<String, HyprwallError> {
+        let path = self.next_wallpaper().await?;
+        self.show(path).await
+    }
+
+    async fn previous(&mut self) -> Result<String, HyprwallError> {
+        let path = self.history.pop().ok_or("No previous wallpaper")?;
+
+        if let Err(e) = self.apply(&path).await {
+            self.history.push(path);
+            return Err(e);
+        }
+
+        if let Some(current) = self.current.replace(path.clone()) {
+            self.queue.push(current);
+        }
+        Ok(path)
+    }
+
+    async fn show(&mut self, path: String) -> Result<String, HyprwallError> {
+        self.apply(&path).await?;
+
+        if let Some(previous) = self.current.replace(path.clone()) {
+            self.history.push(previous);
+            if self.history.len() > HISTORY_SIZE {
+                self.history.remove(0);
+            }
+        }
+        Ok(path)
+    }
+
+    async fn apply(&self, path: &str) -> Result<(), HyprwallError> {
+        crate::set_wallpaper_internal(path, self.monitor.as_deref()).await?;
+        println!("Wallpaper set successfully: {}", path);
+        crate::remember_wallpaper(path, self.monitor.as_deref());
+        Ok(())
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "state: {}\ninterval: {}\nwallpaper: {}\nqueued: {}\nschedule: {}",
+            if self.paused { "paused" } else { "running" },
+            self.interval.map_or("off".to_string(), |interval| format!(
+                "{}s",
+                interval.as_secs()
+            )),
+            self.current.as_deref().unwrap_or("none"),
+            self.queue.len(),
+            self.active_entry()
+                .map_or("none".to_string(), |entry| entry.to_string())
+        )
+    }
+
+    async fn handle(&mut self, request: Request) -> Result<String, HyprwallError> {
+        match request {
+            Request::Next => self.advance().await,
+            Request::Prev => self.previous().await,
+            Request::Pause => {
+                self.paused = true;
+                Ok("Rotation paused".to_string())
+            }
+            Request::Resume => {
+                self.paused = false;
+                Ok("Rotation resumed".to_string())
+            }
+            Request::Set(path) => self.show(config::with_tilde(&path)).await,
+            Request::Status => Ok(self.status()),
+            Request::Reload => {
+                crate::load_wallpaper_backend();
+                self.interval = rotation_interval(self.requested_interval, &config::load());
+                self.schedule = load_schedule();
+                self.queue.clear();
+                Ok("Configuration reloaded".to_string())
+            }
+        }
+    }
+}
+
+pub async fn run(interval: Option<Duration>, monitor: Option<String>) -> Result<(), HyprwallError> {
+    let listener = ipc::bind().await?;
+    let (sender, mut receiver) = mpsc::channel(16);
+    tokio::spawn(ipc::serve(listener, sender));
+
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| format!("Failed to listen for SIGTERM: {}", e))?;
+
+    let (change_sender, mut changes) = mpsc::unbounded_channel();
+    let mut slideshow = Slideshow::new(interval, monitor);
+    slideshow.watch(&change_sender);
+    let mut ticker = new_ticker(slideshow.interval, Instant::now());
+
+    match slideshow.interval {
+        Some(interval) => println!(
+            "Rotating wallpapers every {}s, listening on {}",
+            interval.as_secs(),
+            ipc::socket_path().display()
+        ),
+        None => println!(
+            "Rotation is off, listening on {}",
+            ipc::socket_path().display()
+        ),
+    }
+
+    loop {
+        let until_schedule_change = slideshow.until_schedule_change();
+
+        tokio::select! {
+            _ = sleep_or_wait(until_schedule_change) => {
+                // Sun times move every day, so recompute them at each switch
+                slideshow.schedule = load_schedule();
+                slideshow.queue.clear();
+                slideshow.watch(&change_sender);
+                if let Err(e) = slideshow.advance().await {
+                    eprintln!("Error switching to scheduled wallpaper: {}", e);
+                }
+                reset(&mut ticker);
+            }
+            _ = tick(&mut ticker) => {
+                if !slideshow.paused {
+                    if let Err(e) = slideshow.advance().await {
+                        eprintln!("Error rotating wallpaper: {}", e);
+                    }
+                }
+            }
+            Some((request, reply)) = receiver.recv() => {
+                let restarts_timer = matches!(request, Request::Next | Request::Prev | Request::Set(_));
+                let reloads = matches!(request, Request::Reload);
+                let result = slideshow.handle(request).await;
+                if reloads {
+                    slideshow.watch(&change_sender);
+                    let start = Instant::now() + slideshow.interval.unwrap_or_default();
+                    ticker = new_ticker(slideshow.interval, start);
+                } else if restarts_timer && result.is_ok() {
+                    reset(&mut ticker);
+                }
+                let _ = reply.send(result.map_err(|e| e.to_string()));
+            }
+            Some(changes) = changes.recv() => slideshow.sync(changes),
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    ipc::remove_socket();
+    Ok(())
+}
+
+fn load_schedule() -> Option<Schedule> {
+    Schedule::load().unwrap_or_else(|e| {
+        eprintln!("Ignoring schedule: {}", e);
+        None
+    })
+}
+
+// An explicit --interval wins, then the active profile's rotation policy.
+fn rotation_interval(requested: Option<Duration>, config: &Config) -> Option<Duration> {
+    if requested.is_some() {
+        return requested;
+    }
+    match config.active_profile().and_then(|profile| profile.rotation) {
+        Some(Rotation::Off) => None,
+        Some(Rotation::Every(interval)) => Some(interval),
+        None => Some(DEFAULT_INTERVAL),
+    }
+}
+
+fn new_ticker(interval: Option<Duration>, start: Instant) -> Option<Interval> {
+    let mut ticker = tokio::time::interval_at(start, interval?);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    Some(ticker)
+}
+
+async fn tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn reset(ticker: &mut Option<Interval>) {
+    if let Some(ticker) = ticker {
+        ticker.reset();
+    }
+}
+
+async fn sleep_or_wait(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let too_long = || {
+        format!(
+            "Interval must be at most {}d",
+            MAX_INTERVAL.as_secs() / 86400
+        )
+    };
+    let mut seconds = 0u64;
+    let mut number = String::new();
+    let mut has_unit = false;
+
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("Invalid unit '{}', expected s, m, h or d", c)),
+        };
+        if number.is_empty() {
+            return Err(format!("Missing number before '{}'", c));
+        }
+        // Digits alone only fail to parse when they overflow
+        let amount: u64 = number.parse().map_err(|_| too_long())?;
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|amount| seconds.checked_add(amount))
+            .ok_or_else(too_long)?;
+        number.clear();
+        has_unit = true;
+    }
+
+    // A plain number is seconds, but in `1h30` the unit was most likely forgotten
+    if !number.is_empty() && has_unit {
+        return Err(format!(
+            "Missing unit after '{}', expected s, m, h or d",
+            number
+        ));
+    }
+    if !number.is_empty() {
+        let amount: u64 = number.parse().map_err(|_| too_long())?;
+        seconds = seconds.checked_add(amount).ok_or_else(too_long)?;
+    }
+
+    if seconds == 0 {
+        return Err("Interval must be greater than zero".to_string());
+    }
+    if seconds > MAX_INTERVAL.as_secs() {
+        return Err(too_long());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(value: &str) -> Result<u64, String> {
+        parse_interval(value).map(|interval| interval.as_secs())
+    }
+
+    #[test]
+    fn intervals_add_up_their_units() {
+        assert_eq!(seconds("30s"), Ok(30));
+        assert_eq!(seconds("5m"), Ok(5 * 60));
+        assert_eq!(seconds("1h30m"), Ok(90 * 60));
+        assert_eq!(seconds("1d2h"), Ok(26 * 60 * 60));
+        assert_eq!(seconds(" 90 "), Ok(90));
+        assert_eq!(seconds("365d"), Ok(MAX_INTERVAL.as_secs()));
+    }
+
+    #[test]
+    fn bad_intervals_are_rejected() {
+        assert_eq!(
+            seconds("1h30"),
+            Err("Missing unit after '30', expected s, m, h or d".to_string())
+        );
+        assert_eq!(
+            seconds("0m"),
+            Err("Interval must be greater than zero".to_string())
+        );
+        assert_eq!(
+            seconds(""),
+            Err("Interval must be greater than zero".to_string())
+        );
+        assert_eq!(
+            seconds("5w"),
+            Err("Invalid unit 'w', expected s, m, h or d".to_string())
+        );
+        assert_eq!(seconds("m"), Err("Missing number before 'm'".to_string()));
+        assert_eq!(
+            seconds("-5m"),
+            Err("Invalid unit '-', expected s, m, h or d".to_string())
+        );
+    }
+
+    #[test]
+    fn huge_intervals_are_rejected_without_overflowing() {
+        let too_long = Err("Interval must be at most 365d".to_string());
+        assert_eq!(seconds("366d"), too_long);
+        assert_eq!(seconds("99999999999999999999s"), too_long);
+        assert_eq!(seconds("999999999999999999d"), too_long);
+        assert_eq!(seconds("18446744073709551615s1s"), too_long);
+    }
+
+    fn with_profile(interval: &str) -> Config {
+        Config::parse(&format!(
+            "[Settings]\nprofile = work\n\n[Profile.work]\ninterval = {}\n",
+            interval
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn requested_interval_wins_over_the_profile() {
+        let requested = Some(Duration::from_secs(10));
+        assert_eq!(rotation_interval(requested, &with_profile("1h")), requested);
+        assert_eq!(
+            rotation_interval(requested, &Config::parse("").unwrap()),
+            requested
+        );
+    }
+
+    #[test]
+    fn profile_interval_applies_without_a_requested_one() {
+        assert_eq!(
+            rotation_interval(None, &with_profile("1h")),
+            Some(Duration::from_secs(60 * 60))
+        );
+        assert_eq!(rotation_interval(None, &with_profile("off")), None);
+        assert_eq!(
+            rotation_interval(None, &Config::parse("").unwrap()),
+            Some(DEFAULT_INTERVAL)
+        );
+    }
+}
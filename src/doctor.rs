@@ -0,0 +1,307 @@
+use crate::backend::{self, Backend, Cmd, Environment};
+use crate::config::{self, Config};
+use crate::error::HyprwallError;
+use crate::ipc;
+use crate::monitor;
+use crate::scan;
+use crate::schedule::Schedule;
+use gtk::gdk_pixbuf::Pixbuf;
+use std::path::PathBuf;
+
+// Past this, problem paths are counted rather than listed.
+const MAX_LISTED: usize = 10;
+
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("\n{}", title);
+    }
+
+    fn ok(&self, message: impl AsRef<str>) {
+        println!("  ok  {}", message.as_ref());
+    }
+
+    // Worth knowing, but nothing is broken.
+    fn note(&self, message: impl AsRef<str>) {
+        println!("  --  {}", message.as_ref());
+    }
+
+    fn problem(&mut self, message: impl AsRef<str>) {
+        self.problems += 1;
+        println!("  !!  {}", message.as_ref());
+    }
+
+    fn detail(&self, message: impl AsRef<str>) {
+        println!("        {}", message.as_ref());
+    }
+}
+
+// Checks everything hyprwall depends on and prints what it finds, returning
+// how many problems there were.
+pub async fn run() -> usize {
+    let mut report = Report::default();
+    let environment = Environment::detect();
+
+    report.section("Session");
+    if environment.is_known() {
+        report.ok(format!("{} session", environment));
+    } else {
+        report.problem("No graphical session found, WAYLAND_DISPLAY and DISPLAY are unset");
+    }
+
+    let config = check_config(&mut report);
+    let configured = config.as_ref().and_then(Config::backend);
+    check_backends(&mut report, &environment, configured).await;
+    check_hyprpaper(&mut report, configured).await;
+    if let Some(config) = &config {
+        check_folders(&mut report, config).await;
+    }
+    check_monitors(&mut report).await;
+
+    report.section("Daemon");
+    match ipc::send(&ipc::Request::Status).await {
+        Ok(status) => report.ok(status.lines().next().unwrap_or("running")),
+        Err(_) => report.note(format!(
+            "Not running, nothing listens on {}",
+            ipc::socket_path().display()
+        )),
+    }
+
+    println!();
+    match report.problems {
+        0 => println!("No problems found"),
+        1 => println!("1 problem found"),
+        n => println!("{} problems found", n),
+    }
+    report.problems
+}
+
+fn check_config(report: &mut Report) -> Option<Config> {
+    report.section("Config");
+    let path = config::path();
+    let shown = config::with_tilde(&path.to_string_lossy());
+    if !path.exists() {
+        report.note(format!("{} does not exist, defaults are used", shown));
+    }
+
+    let config = match Config::open(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            report.problem(format!("{}: {}", shown, e));
+            return None;
+        }
+    };
+    report.ok(format!("{} is valid", shown));
+
+    if let Err(e) = Schedule::load() {
+        report.problem(format!("[Schedule]: {}", e));
+    }
+    Some(config)
+}
+
+async fn check_backends(
+    report: &mut Report,
+    environment: &Environment,
+    configured: Option<&'static dyn Backend>,
+) {
+    report.section("Backends");
+    for backend in backend::BACKENDS {
+        let is_configured = configured.is_some_and(|c| backend::same(c, *backend));
+        let name = if is_configured {
+            format!("{} (configured)", backend.display_name())
+        } else {
+            backend.display_name().to_string()
+        };
+
+        let availability = backend::availability(*backend, environment);
+        if availability.is_available() {
+            let mut versions = Vec::new();
+            for program in backend.requirements().programs {
+                versions.push(version(program).await);
+            }
+            report.ok(format!("{}: {}", name, versions.join(", ")));
+        } else if is_configured {
+            report.problem(format!("{}: {}", name, availability));
+        } else {
+            report.note(format!("{}: {}", name, availability));
+        }
+    }
+
+    if configured.is_none() {
+        match backend::pick(environment) {
+            Some(backend) => report.note(format!(
+                "No backend configured, {} would be used",
+                backend.display_name()
+            )),
+            None => report.problem("No backend configured and none is usable in this session"),
+        }
+    }
+}
+
+// The first line a program prints about its version, or where it was found
+// for programs that can't be asked without starting them.
+async fn version(program: &'static str) -> String {
+    let args: &[&str] = match program {
+        "hyprctl" => &["version"],
+        "swaybg" => &["-v"],
+        "swww" | "feh" | "setwallpaper" => &["--version"],
+        _ => &[],
+    };
+    let found = || {
+        backend::find_program(program)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| program.to_string())
+    };
+    if args.is_empty() {
+        return found();
+    }
+
+    let Ok(output) = Cmd::new(program).args(args).output().await else {
+        return found();
+    };
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    String::from_utf8_lossy(&text)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map_or_else(found, String::from)
+}
+
+async fn check_hyprpaper(report: &mut Report, configured: Option<&'static dyn Backend>) {
+    if backend::find_program("hyprpaper").is_none() {
+        return;
+    }
+    report.section("Hyprpaper IPC");
+    let uses_hyprpaper = configured.is_some_and(|c| backend::same(c, &backend::Hyprpaper));
+
+    if backend::hyprpaper::ipc_disabled() {
+        let message = format!(
+            "ipc is turned off in {}, hyprwall can't set wallpapers through hyprpaper",
+            config::with_tilde(&backend::hyprpaper::config_path().to_string_lossy())
+        );
+        if uses_hyprpaper {
+            report.problem(message);
+        } else {
+            report.note(message);
+        }
+    }
+
+    if !backend::is_process_running("hyprpaper").await {
+        report.note("hyprpaper is not running, it is started when a wallpaper is set");
+        return;
+    }
+    match backend::hyprpaper::check_ipc().await {
+        Ok(()) => report.ok("hyprctl reaches hyprpaper"),
+        Err(HyprwallError::BackendNotInstalled { program }) => {
+            report.problem(format!("{} is not installed", program))
+        }
+        Err(e) => report.problem(format!("hyprctl can't reach hyprpaper: {}", e)),
+    }
+}
+
+async fn check_folders(report: &mut Report, config: &Config) {
+    report.section("Folders");
+    let sources = config.sources();
+    if sources.is_empty() {
+        report.problem("No wallpaper folder in the config");
+        return;
+    }
+
+    let options = config.scan_options();
+    for source in sources {
+        let shown = format!(
+            "{} ({})",
+            source.name,
+            config::with_tilde(&source.path.to_string_lossy())
+        );
+        if !source.path.is_dir() {
+            report.problem(format!("{}: not a directory", shown));
+            continue;
+        }
+
+        // Reading the header is enough to tell whether gdk-pixbuf can decode it.
+        // Images taken at their extension were never opened, so try that first.
+        let path = source.path.clone();
+        let scanned = tokio::task::spawn_blocking(move || {
+            let scan::Scan {
+                images,
+                mut unreadable,
+            } = scan::scan(&path, scan::ALL, &options);
+            let mut undecodable = Vec::new();
+            for image in &images {
+                if std::fs::File::open(image).is_err() {
+                    unreadable.push(image.clone());
+                } else if Pixbuf::file_info(image).is_none() {
+                    undecodable.push(image.clone());
+                }
+            }
+            (images.len(), unreadable, undecodable)
+        })
+        .await;
+        let Ok((count, unreadable, undecodable)) = scanned else {
+            report.problem(format!("{}: failed to scan", shown));
+            continue;
+        };
+
+        if count == 0 {
+            report.problem(format!("{}: no images found", shown));
+        } else {
+            report.ok(format!("{}: {} images", shown, count));
+        }
+
+        if !unreadable.is_empty() {
+            report.problem(format!(
+                "{}: {} files or folders can't be opened, check their permissions and symlinks",
+                shown,
+                unreadable.len()
+            ));
+            list_paths(report, &unreadable);
+        }
+        if !undecodable.is_empty() {
+            report.problem(format!(
+                "{}: {} images can't be decoded, they are damaged or their gdk-pixbuf loader is missing",
+                shown,
+                undecodable.len()
+            ));
+            list_paths(report, &undecodable);
+        }
+    }
+}
+
+fn list_paths(report: &mut Report, paths: &[PathBuf]) {
+    for path in paths.iter().take(MAX_LISTED) {
+        report.detail(config::with_tilde(&path.to_string_lossy()));
+    }
+    if paths.len() > MAX_LISTED {
+        report.detail(format!("and {} more", paths.len() - MAX_LISTED));
+    }
+}
+
+async fn check_monitors(report: &mut Report) {
+    report.section("Monitors");
+    let mut found = false;
+    for (program, monitors) in monitor::each_detector().await {
+        match monitors {
+            _ if backend::find_program(program).is_none() => {
+                report.note(format!("{}: not installed", program))
+            }
+            Some(monitors) if !monitors.is_empty() => {
+                found = true;
+                report.ok(format!("{}: {}", program, monitors.join(", ")));
+            }
+            Some(_) => report.note(format!("{}: no monitors reported", program)),
+            None => report.note(format!("{}: failed to run", program)),
+        }
+    }
+    if !found {
+        report.problem(HyprwallError::NoMonitors.to_string());
+    }
+}
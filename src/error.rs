@@ -0,0 +1,205 @@
+use crate::config::ConfigError;
+use std::fmt;
+
+// Failures the user can do something about get their own variant, so the CLI
+// can exit with a distinct code and the GUI can offer a way out. Everything
+// else stays a plain message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HyprwallError {
+    BackendNotInstalled {
+        program: String,
+    },
+    BackendFailedToStart {
+        program: String,
+    },
+    CommandFailed {
+        command: String,
+        status: Option<i32>,
+        stdout: String,
+        stderr: String,
+    },
+    NoMonitors,
+    ConfigParse(String),
+    ImageUnreadable {
+        path: String,
+        reason: String,
+    },
+    Other(String),
+}
+
+impl HyprwallError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HyprwallError::Other(_) => 1,
+            HyprwallError::ConfigParse(_) => 2,
+            HyprwallError::BackendNotInstalled { .. } => 3,
+            HyprwallError::BackendFailedToStart { .. } => 4,
+            HyprwallError::CommandFailed { .. } => 5,
+            HyprwallError::NoMonitors => 6,
+            HyprwallError::ImageUnreadable { .. } => 7,
+        }
+    }
+
+    // What the user can try next, shown under the error on the CLI and in the
+    // GUI dialog.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            HyprwallError::BackendNotInstalled { program } => Some(format!(
+                "Install {} with your distribution's package manager, or switch to another backend.",
+                program
+            )),
+            HyprwallError::BackendFailedToStart { program } => Some(format!(
+                "Check that {} works in this session by running it from a terminal, or switch to another backend.",
+                program
+            )),
+            HyprwallError::CommandFailed { .. } => {
+                Some("Switching to another backend may work around it.".to_string())
+            }
+            HyprwallError::NoMonitors => Some(
+                "Install hyprctl, wlr-randr or xrandr, or set the wallpaper on all monitors."
+                    .to_string(),
+            ),
+            HyprwallError::ConfigParse(_) => Some(format!(
+                "Fix the config file at {}.",
+                crate::config::path().display()
+            )),
+            HyprwallError::ImageUnreadable { .. } => {
+                Some("Pick another wallpaper, the file may be damaged.".to_string())
+            }
+            HyprwallError::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for HyprwallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HyprwallError::BackendNotInstalled { program } => {
+                write!(f, "{} is not installed", program)
+            }
+            HyprwallError::BackendFailedToStart { program } => {
+                write!(f, "{} failed to start or crashed immediately", program)
+            }
+            HyprwallError::CommandFailed {
+                command,
+                status,
+                stdout,
+                stderr,
+            } => {
+                match status {
+                    Some(code) => {
+                        write!(f, "Command '{}' failed with exit code {}", command, code)?
+                    }
+                    None => write!(f, "Command '{}' was killed", command)?,
+                }
+                for (name, output) in [("Stdout", stdout), ("Stderr", stderr)] {
+                    if !output.trim().is_empty() {
+                        write!(f, "\n{}: {}", name, output.trim())?;
+                    }
+                }
+                Ok(())
+            }
+            HyprwallError::NoMonitors => {
+                write!(
+                    f,
+                    "Failed to detect monitors with hyprctl, wlr-randr or xrandr"
+                )
+            }
+            HyprwallError::ConfigParse(message) => {
+                write!(f, "Failed to read config file: {}", message)
+            }
+            HyprwallError::ImageUnreadable { path, reason } => {
+                write!(f, "Failed to read {}: {}", path, reason)
+            }
+            HyprwallError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<String> for HyprwallError {
+    fn from(message: String) -> Self {
+        HyprwallError::Other(message)
+    }
+}
+
+impl From<&str> for HyprwallError {
+    fn from(message: &str) -> Self {
+        HyprwallError::Other(message.to_string())
+    }
+}
+
+// Only a config that can't be parsed is the user's to fix, failing to read it
+// at all is reported as is.
+impl From<ConfigError> for HyprwallError {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::Io(message) => HyprwallError::Other(message),
+            invalid @ ConfigError::Invalid { .. } => {
+                HyprwallError::ConfigParse(invalid.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scripts check these, so they must not shift.
+    #[test]
+    fn exit_codes_are_stable() {
+        let cases = [
+            (HyprwallError::Other("failed".to_string()), 1),
+            (HyprwallError::ConfigParse("line 3".to_string()), 2),
+            (
+                HyprwallError::BackendNotInstalled {
+                    program: "swww".to_string(),
+                },
+                3,
+            ),
+            (
+                HyprwallError::BackendFailedToStart {
+                    program: "swww".to_string(),
+                },
+                4,
+            ),
+            (
+                HyprwallError::CommandFailed {
+                    command: "swww img".to_string(),
+                    status: Some(1),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                },
+                5,
+            ),
+            (HyprwallError::NoMonitors, 6),
+            (
+                HyprwallError::ImageUnreadable {
+                    path: "/walls/broken.png".to_string(),
+                    reason: "truncated".to_string(),
+                },
+                7,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn invalid_config_is_a_parse_error() {
+        let error = HyprwallError::from(ConfigError::invalid(3, Some("sort"), "unknown sort"));
+        assert_eq!(
+            error,
+            HyprwallError::ConfigParse("line 3, key 'sort': unknown sort".to_string())
+        );
+        assert_eq!(error.exit_code(), 2);
+    }
+
+    #[test]
+    fn unreadable_config_is_reported_as_is() {
+        let error = HyprwallError::from(ConfigError::Io("Permission denied".to_string()));
+        assert_eq!(error, HyprwallError::Other("Permission denied".to_string()));
+        assert_eq!(error.exit_code(), 1);
+    }
+}
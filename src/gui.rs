@@ -1,127 +1,395 @@
-use crossbeam_channel::unbounded;
+use crossbeam_channel::{unbounded, Sender};
 use glib::ControlFlow;
 use gtk::{
     gdk::{self, Texture},
-    gdk_pixbuf::Pixbuf,
     gio, glib,
     prelude::*,
-    Application, ApplicationWindow, Box as GtkBox, Button, ComboBoxText, EventControllerMotion,
-    FlowBox, FlowBoxChild, Image, MessageDialog, ScrolledWindow, SearchEntry,
+    Application, ApplicationWindow, Box as GtkBox, Button, ComboBoxText, CustomFilter,
+    FilterListModel, GridView, Image, ListItem, MessageDialog, NoSelection, ScrolledWindow,
+    SearchEntry, SignalListItemFactory, StringObject,
 };
-use parking_lot::Mutex;
-use rand::seq::SliceRandom;
-use rayon::prelude::*;
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use rand::Rng;
 use std::{
     cell::RefCell,
-    collections::{BTreeMap, VecDeque},
-    fs,
-    io::{Read, Write},
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
     rc::Rc,
-    sync::atomic::{AtomicBool, Ordering},
     sync::Arc,
+    time::Duration,
 };
 
-use crate::WallpaperBackend;
+use crate::backend;
+use crate::config;
+use crate::error::HyprwallError;
+use crate::scan;
+use crate::sort;
+use crate::thumbnail;
+use crate::watch;
+
+const ALL_MONITORS: &str = "all";
+const ALL_SOURCES: &str = "all";
+// Application actions the error dialogs trigger
+const CHOOSE_BACKEND: &str = "choose-backend";
+const ALL_MONITORS_ACTION: &str = "all-monitors";
+const THUMBNAIL_SIZE: i32 = 250;
+// Wallpapers are ranked and shown this many at a time while a folder loads
+const LOAD_BATCH: usize = 200;
+
+lazy_static! {
+    static ref SELECTED_MONITOR: Mutex<Option<String>> = Mutex::new(None);
+}
+
+#[derive(Default)]
+struct GridFilter {
+    search: String,
+    source: Option<PathBuf>,
+}
+
+impl GridFilter {
+    fn matches(&self, path: &str) -> bool {
+        // Both sides are tilde paths, as the source combo ids are
+        if let Some(source) = &self.source {
+            if !Path::new(path).starts_with(source) {
+                return false;
+            }
+        }
+
+        if self.search.is_empty() {
+            return true;
+        }
+
+        Path::new(path)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().to_lowercase().contains(&self.search))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FolderAction {
+    Change,
+    Add,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum TextureKind {
+    Thumbnail,
+    Preview,
+}
 
-const CONFIG_FILE: &str = "~/.config/hyprwall/config.ini";
-const CACHE_SIZE: usize = 100;
+type CacheKey = (PathBuf, TextureKind);
 
+// Bounded by the estimated size of the decoded textures rather than their
+// count, so a few full-size previews can't crowd out memory. Every use stamps
+// an entry with the next tick, so the least recently used is the first in
+// `order` and a hit costs no more than a lookup.
 struct ImageCache {
-    cache: BTreeMap<PathBuf, gdk::Texture>,
-    order: VecDeque<PathBuf>,
+    cache: HashMap<CacheKey, (gdk::Texture, u64)>,
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    bytes: usize,
+    budget: usize,
+    loading: HashMap<CacheKey, Arc<Pending>>,
+}
+
+// A decode in progress, which other threads asking for the same image wait on
+// instead of decoding it again.
+#[derive(Default)]
+struct Pending {
+    result: Mutex<Option<Option<Texture>>>,
+    done: Condvar,
+}
+
+// Ends a decode when dropped, so a decode that panics still clears its entry
+// and its waiters see it as failed rather than block forever.
+struct Decoding<'a> {
+    cache: &'a Mutex<ImageCache>,
+    key: CacheKey,
+    pending: Arc<Pending>,
+    texture: Option<Texture>,
 }
 
+// Holds the wallpapers as tilde paths in a list model. The grid only creates
+// widgets for the rows on screen and asks for their thumbnails as they bind.
+// The store is unordered, a sort model on top orders it by ranks that are
+// read on a worker thread before a wallpaper is added.
 struct ImageLoader {
-    queue: VecDeque<PathBuf>,
-    current_folder: Option<PathBuf>,
+    store: gio::ListStore,
+    ranks: Rc<RefCell<HashMap<String, Option<u64>>>>,
+    sorter: gtk::CustomSorter,
+    key: sort::SortKey,
+    // Set until every batch of the current load is in the store
+    loading: bool,
+    sources: Vec<PathBuf>,
     cache: Arc<Mutex<ImageCache>>,
-    cancel_flag: Option<Arc<AtomicBool>>,
+    // Images bound to a grid cell, decodes for anything else are skipped
+    visible: Arc<Mutex<HashSet<PathBuf>>>,
+    watcher: Option<watch::Watcher>,
+    changes: Sender<watch::Changes>,
+    // Bumped on every load, so a slow scan can't overwrite a newer one
+    generation: u64,
 }
 
 impl ImageCache {
-    fn new() -> Self {
+    fn new(budget: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
-            order: VecDeque::with_capacity(CACHE_SIZE),
+            cache: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            budget,
+            loading: HashMap::new(),
         }
     }
 
-    fn get(&mut self, path: &Path) -> Option<gdk::Texture> {
-        self.cache.get(path).cloned().inspect(|_| {
-            self.order.retain(|p| p != path);
-            self.order.push_front(path.to_path_buf());
-        })
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, path: &Path, kind: TextureKind) -> Option<gdk::Texture> {
+        let key = (path.to_path_buf(), kind);
+        let tick = self.next_tick();
+        let (texture, used) = self.cache.get_mut(&key)?;
+        let last_used = std::mem::replace(used, tick);
+        let texture = texture.clone();
+        self.order.remove(&last_used);
+        self.order.insert(tick, key);
+        Some(texture)
     }
 
-    fn insert(&mut self, path: PathBuf, texture: gdk::Texture) {
-        if self.cache.len() >= CACHE_SIZE {
-            if let Some(old_path) = self.order.pop_back() {
-                self.cache.remove(&old_path);
+    fn insert(&mut self, path: PathBuf, kind: TextureKind, texture: gdk::Texture) {
+        let key = (path, kind);
+        self.evict(&key);
+
+        let size = texture_bytes(&texture);
+        // Anything bigger than the whole budget is shown but not kept
+        if size > self.budget {
+            return;
+        }
+        while self.bytes + size > self.budget {
+            let Some((_, old_key)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((old, _)) = self.cache.remove(&old_key) {
+                self.bytes -= texture_bytes(&old);
             }
         }
-        self.cache.insert(path.clone(), texture);
-        self.order.push_front(path);
+
+        let tick = self.next_tick();
+        self.bytes += size;
+        self.cache.insert(key.clone(), (texture, tick));
+        self.order.insert(tick, key);
     }
 
-    fn get_or_insert(&mut self, path: &Path, max_size: i32) -> Option<Texture> {
-        self.get(path).or_else(|| {
-            let is_gif = path
-                .extension()
-                .and_then(|e| e.to_str())
-                .map_or(false, |ext| ext.eq_ignore_ascii_case("gif"));
+    fn evict(&mut self, key: &CacheKey) {
+        if let Some((texture, used)) = self.cache.remove(key) {
+            self.bytes -= texture_bytes(&texture);
+            self.order.remove(&used);
+        }
+    }
 
-            let texture = if is_gif {
-                Texture::from_file(&gio::File::for_path(path)).ok()?
-            } else {
-                let pixbuf = Pixbuf::from_file_at_scale(path, max_size, max_size, true).ok()?;
-                Texture::for_pixbuf(&pixbuf)
-            };
+    fn remove(&mut self, path: &Path) {
+        for kind in [TextureKind::Thumbnail, TextureKind::Preview] {
+            self.evict(&(path.to_path_buf(), kind));
+        }
+    }
 
-            self.insert(path.to_path_buf(), texture.clone());
-            Some(texture)
+    fn get_or_load(cache: &Mutex<Self>, path: &Path, max_size: i32) -> Option<Texture> {
+        Self::get_or_decode(cache, path, || {
+            thumbnail::load(path, max_size).map(|pixbuf| Texture::for_pixbuf(&pixbuf))
         })
     }
+
+    // The lock is only held for the lookup and the insert, so thumbnails
+    // decode in parallel.
+    fn get_or_decode(
+        cache: &Mutex<Self>,
+        path: &Path,
+        decode: impl FnOnce() -> Option<Texture>,
+    ) -> Option<Texture> {
+        let key = (path.to_path_buf(), TextureKind::Thumbnail);
+        let pending = {
+            let mut cache = cache.lock();
+            if let Some(texture) = cache.get(path, TextureKind::Thumbnail) {
+                return Some(texture);
+            }
+            if let Some(pending) = cache.loading.get(&key).cloned() {
+                drop(cache);
+                return pending.wait();
+            }
+            let pending = Arc::new(Pending::default());
+            cache.loading.insert(key.clone(), Arc::clone(&pending));
+            pending
+        };
+
+        let mut decoding = Decoding {
+            cache,
+            key,
+            pending,
+            texture: None,
+        };
+        decoding.texture = decode();
+        decoding.texture.clone()
+    }
+}
+
+impl Drop for Decoding<'_> {
+    fn drop(&mut self) {
+        {
+            let mut cache = self.cache.lock();
+            cache.loading.remove(&self.key);
+            if let Some(texture) = &self.texture {
+                cache.insert(self.key.0.clone(), self.key.1, texture.clone());
+            }
+        }
+        self.pending.finish(self.texture.take());
+    }
+}
+
+impl Pending {
+    fn wait(&self) -> Option<Texture> {
+        let mut result = self.result.lock();
+        while result.is_none() {
+            self.done.wait(&mut result);
+        }
+        result.clone().flatten()
+    }
+
+    fn finish(&self, texture: Option<Texture>) {
+        *self.result.lock() = Some(texture);
+        self.done.notify_all();
+    }
+}
+
+// Decoded RGBA, which is what the texture holds on the GPU or in memory.
+fn texture_bytes(texture: &gdk::Texture) -> usize {
+    texture.width().max(0) as usize * texture.height().max(0) as usize * 4
 }
 
 impl ImageLoader {
-    fn new() -> Self {
+    fn new(changes: Sender<watch::Changes>) -> Self {
+        let ranks: Rc<RefCell<HashMap<String, Option<u64>>>> = Rc::default();
+        let ranks_clone = Rc::clone(&ranks);
+        let sorter = gtk::CustomSorter::new(move |a, b| {
+            let path = |item: &glib::Object| {
+                item.downcast_ref::<StringObject>()
+                    .map(|path| path.string().to_string())
+                    .unwrap_or_default()
+            };
+            let (a, b) = (path(a), path(b));
+            let ranks = ranks_clone.borrow();
+            let rank = |path: &str| ranks.get(path).copied().flatten();
+            sort::compare(Path::new(&a), rank(&a), Path::new(&b), rank(&b)).into()
+        });
+
         Self {
-            queue: VecDeque::new(),
-            current_folder: None,
-            cache: Arc::new(Mutex::new(ImageCache::new())),
-            cancel_flag: None,
+            store: gio::ListStore::new::<StringObject>(),
+            ranks,
+            sorter,
+            key: config::load().sort(),
+            loading: false,
+            sources: Vec::new(),
+            cache: Arc::new(Mutex::new(ImageCache::new(
+                config::load().texture_cache_size(),
+            ))),
+            visible: Arc::new(Mutex::new(HashSet::new())),
+            watcher: None,
+            changes,
+            generation: 0,
         }
     }
 
-    fn load_folder(&mut self, folder: &Path) {
-        if let Some(flag) = self.cancel_flag.as_ref() {
-            flag.store(true, Ordering::Relaxed)
-        }
-        self.queue.clear();
-        self.current_folder = Some(folder.to_path_buf());
+    // Switches to the given folders and starts watching them. The scan itself
+    // runs in the background, see load_images.
+    fn load_sources(
+        &mut self,
+        sources: &[PathBuf],
+        formats: &'static [scan::Format],
+        options: scan::Options,
+        key: sort::SortKey,
+    ) -> u64 {
+        self.sources = sources.to_vec();
+        self.generation += 1;
+        self.key = key;
+        self.loading = true;
+
+        // Stop the old watcher first so it can't report into the new grid
+        drop(self.watcher.take());
+        let changes = self.changes.clone();
+        self.watcher = watch::watch(sources.to_vec(), formats, options, move |c| {
+            let _ = changes.send(c);
+        })
+        .map_err(|e| eprintln!("Not watching wallpaper folders: {}", e))
+        .ok();
+        self.generation
+    }
 
-        if let Ok(entries) = fs::read_dir(folder) {
-            self.queue.extend(entries.filter_map(|entry| {
-                entry.ok().and_then(|e| {
-                    let path = e.path();
-                    if path.is_file() {
-                        let extension = path
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            .map(|s| s.to_lowercase());
+    fn clear(&self) {
+        self.store.remove_all();
+        self.ranks.borrow_mut().clear();
+    }
 
-                        match extension.as_deref() {
-                            Some("png" | "jpg" | "jpeg" | "gif") => Some(path),
-                            _ => None,
-                        }
-                    } else {
-                        None
+    // The sort model puts each new wallpaper in its place.
+    fn add(&self, ranked: Vec<(PathBuf, Option<u64>)>) {
+        let wallpapers: Vec<StringObject> = {
+            let mut ranks = self.ranks.borrow_mut();
+            ranked
+                .into_iter()
+                .filter_map(|(path, rank)| {
+                    let path = config::with_tilde(&path.to_string_lossy());
+                    if ranks.contains_key(&path) {
+                        return None;
                     }
+                    ranks.insert(path.clone(), rank);
+                    Some(StringObject::new(&path))
                 })
-            }));
+                .collect()
+        };
+        self.store.splice(self.store.n_items(), 0, &wallpapers);
+    }
+
+    // New ranks for wallpapers already shown, which moves them if needed.
+    fn update(&self, ranked: Vec<(PathBuf, Option<u64>)>) {
+        let mut ranks = self.ranks.borrow_mut();
+        let ranked: HashSet<String> = ranked
+            .into_iter()
+            .filter_map(|(path, rank)| {
+                let path = config::with_tilde(&path.to_string_lossy());
+                *ranks.get_mut(&path)? = rank;
+                Some(path)
+            })
+            .collect();
+        drop(ranks);
+
+        for position in 0..self.store.n_items() {
+            let Some(path) = self.store.item(position).and_downcast::<StringObject>() else {
+                continue;
+            };
+            if ranked.contains(path.string().as_str()) {
+                // Also rebinds the cell, which loads the new thumbnail
+                self.store.items_changed(position, 1, 1);
+            }
         }
     }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        (0..self.store.n_items())
+            .filter_map(|position| self.store.item(position).and_downcast::<StringObject>())
+            .map(|path| PathBuf::from(shellexpand::tilde(path.string().as_str()).into_owned()))
+            .collect()
+    }
+}
+
+fn rank_all(ranker: &sort::Ranker, paths: Vec<PathBuf>) -> Vec<(PathBuf, Option<u64>)> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let rank = ranker.rank(&path);
+            (path, rank)
+        })
+        .collect()
 }
 
 pub fn build_ui(app: &Application) {
@@ -140,79 +408,240 @@ pub fn build_ui(app: &Application) {
         .vexpand(true)
         .build();
 
-    let flowbox = FlowBox::builder()
-        .valign(gtk::Align::Start)
-        .halign(gtk::Align::Fill)
-        .selection_mode(gtk::SelectionMode::None)
-        .hexpand(true)
-        .vexpand(true)
-        .homogeneous(true)
-        .row_spacing(10)
-        .column_spacing(10)
-        .build();
+    let (change_sender, change_receiver) = unbounded::<watch::Changes>();
+    let image_loader = Rc::new(RefCell::new(ImageLoader::new(change_sender)));
+
+    let grid_filter = Rc::new(RefCell::new(GridFilter::default()));
+    let grid_filter_clone = Rc::clone(&grid_filter);
+    let filter = CustomFilter::new(move |item| {
+        item.downcast_ref::<StringObject>()
+            .is_some_and(|path| grid_filter_clone.borrow().matches(&path.string()))
+    });
+    let sort_model = gtk::SortListModel::new(
+        Some(image_loader.borrow().store.clone()),
+        Some(image_loader.borrow().sorter.clone()),
+    );
+    let filter_model = FilterListModel::new(Some(sort_model), Some(filter.clone()));
+
+    let grid_view = GridView::new(
+        Some(NoSelection::new(Some(filter_model.clone()))),
+        Some(thumbnail_factory(&image_loader.borrow())),
+    );
+    grid_view.set_single_click_activate(true);
+    grid_view.set_max_columns(64);
+    grid_view.connect_activate(|grid_view, position| {
+        let path = grid_view
+            .model()
+            .and_then(|model| model.item(position))
+            .and_downcast::<StringObject>();
+        if let Some(path) = path {
+            crate::set_wallpaper(path.string().into(), selected_monitor());
+        }
+    });
 
-    scrolled_window.set_child(Some(&flowbox));
+    scrolled_window.set_child(Some(&grid_view));
 
-    let flowbox_ref = Rc::new(RefCell::new(flowbox));
-    let image_loader = Rc::new(RefCell::new(ImageLoader::new()));
+    let image_loader_clone = Rc::clone(&image_loader);
+    glib::timeout_add_local(Duration::from_millis(250), move || {
+        while let Ok(changes) = change_receiver.try_recv() {
+            apply_changes(changes, &image_loader_clone);
+        }
+        ControlFlow::Continue
+    });
+
+    let source_combo = ComboBoxText::new();
+    fill_sources(&source_combo);
+
+    let remove_source_button = Button::with_label("Remove folder");
+    remove_source_button.set_visible(false);
+
+    let filter_clone = filter.clone();
+    let grid_filter_clone = Rc::clone(&grid_filter);
+    let remove_source_button_clone = remove_source_button.clone();
+    source_combo.connect_changed(move |combo| {
+        let source = combo
+            .active_id()
+            .filter(|id| id.as_str() != ALL_SOURCES)
+            .map(|id| PathBuf::from(id.as_str()));
+        remove_source_button_clone.set_visible(source.is_some());
+        grid_filter_clone.borrow_mut().source = source;
+        filter_clone.changed(gtk::FilterChange::Different);
+    });
+
+    let image_loader_clone = Rc::clone(&image_loader);
+    let source_combo_clone = source_combo.clone();
+    remove_source_button.connect_clicked(move |_| {
+        if let Some(source) = source_combo_clone.active_id() {
+            config::update(|config| {
+                config.remove_source(&source);
+            });
+            fill_sources(&source_combo_clone);
+            load_images(&config::load().source_paths(), &image_loader_clone);
+        }
+    });
 
     let choose_folder_button = Button::with_label("Change wallpaper folder");
-    let flowbox_clone = Rc::clone(&flowbox_ref);
     let image_loader_clone = Rc::clone(&image_loader);
+    let source_combo_clone = source_combo.clone();
     let window_weak = window.downgrade();
     choose_folder_button.connect_clicked(move |_| {
         if let Some(window) = window_weak.upgrade() {
-            choose_folder(&window, &flowbox_clone, &image_loader_clone);
+            choose_folder(
+                &window,
+                FolderAction::Change,
+                &source_combo_clone,
+                &image_loader_clone,
+            );
+        }
+    });
+
+    let add_folder_button = Button::with_label("Add folder");
+    let image_loader_clone = Rc::clone(&image_loader);
+    let source_combo_clone = source_combo.clone();
+    let window_weak = window.downgrade();
+    add_folder_button.connect_clicked(move |_| {
+        if let Some(window) = window_weak.upgrade() {
+            choose_folder(
+                &window,
+                FolderAction::Add,
+                &source_combo_clone,
+                &image_loader_clone,
+            );
         }
     });
 
     let refresh_button = Button::with_label("Refresh");
-    let flowbox_clone = Rc::clone(&flowbox_ref);
     let image_loader_clone = Rc::clone(&image_loader);
     refresh_button.connect_clicked(move |_| {
-        refresh_images(&flowbox_clone, &image_loader_clone);
+        refresh_images(&image_loader_clone);
+    });
+
+    let sort_combo = ComboBoxText::new();
+    for key in sort::KEYS {
+        sort_combo.append(Some(key.name()), key.label());
+    }
+    sort_combo.set_active_id(Some(config::load().sort().name()));
+
+    let image_loader_clone = Rc::clone(&image_loader);
+    sort_combo.connect_changed(move |combo| {
+        let Some(key) = combo
+            .active_id()
+            .and_then(|id| sort::SortKey::parse(&id).ok())
+        else {
+            return;
+        };
+        config::update(|config| config.set_sort(key));
+        resort(&image_loader_clone, key);
     });
 
     let random_button = Button::with_label("Random");
     let exit_button = Button::with_label("Exit");
 
-    let backend_combo = ComboBoxText::new();
-    backend_combo.append(Some("none"), "None");
-    backend_combo.append(Some("hyprpaper"), "Hyprpaper");
-    backend_combo.append(Some("swaybg"), "Swaybg");
-    backend_combo.append(Some("swww"), "Swww");
-    backend_combo.append(Some("wallutils"), "Wallutils");
-    backend_combo.append(Some("feh"), "Feh");
-
+    let backend_combo = backend_combo();
     let current_backend = *crate::CURRENT_BACKEND.lock();
-    let backend_id = match current_backend {
-        WallpaperBackend::None => "none",
-        WallpaperBackend::Hyprpaper => "hyprpaper",
-        WallpaperBackend::Swaybg => "swaybg",
-        WallpaperBackend::Swww => "swww",
-        WallpaperBackend::Wallutils => "wallutils",
-        WallpaperBackend::Feh => "feh",
-    };
-    backend_combo.set_active_id(Some(backend_id));
+    backend_combo.set_active_id(Some(current_backend.map_or("none", |b| b.name())));
+
+    let monitor_combo = ComboBoxText::new();
+    monitor_combo.append(Some(ALL_MONITORS), "All monitors");
+    monitor_combo.set_active_id(Some(ALL_MONITORS));
+    monitor_combo.set_visible(current_backend.is_some_and(|b| b.capabilities().per_monitor));
+    monitor_combo.connect_changed(|combo| {
+        *SELECTED_MONITOR.lock() = combo
+            .active_id()
+            .filter(|id| id.as_str() != ALL_MONITORS)
+            .map(|id| id.to_string());
+    });
+
+    let backend_combo_clone = backend_combo.clone();
+    let choose_backend = gio::SimpleAction::new(CHOOSE_BACKEND, None);
+    choose_backend.connect_activate(move |_, _| backend_combo_clone.popup());
+    app.add_action(&choose_backend);
+
+    let monitor_combo_clone = monitor_combo.clone();
+    let all_monitors = gio::SimpleAction::new(ALL_MONITORS_ACTION, None);
+    all_monitors.connect_activate(move |_, _| {
+        monitor_combo_clone.set_active_id(Some(ALL_MONITORS));
+    });
+    app.add_action(&all_monitors);
+
+    let monitor_combo_clone = monitor_combo.clone();
+    glib::spawn_future_local(async move {
+        if let Ok(monitors) = crate::monitor::get_monitors().await {
+            for monitor in monitors {
+                monitor_combo_clone.append(Some(&monitor), &monitor);
+            }
+        }
+    });
 
-    let flowbox_clone_backend = Rc::clone(&flowbox_ref);
     let image_loader_clone_backend = Rc::clone(&image_loader);
+    let monitor_combo_clone = monitor_combo.clone();
     backend_combo.connect_changed(move |combo| {
         if let Some(active_id) = combo.active_id() {
             let backend = match active_id.as_str() {
-                "none" => WallpaperBackend::None,
-                "hyprpaper" => WallpaperBackend::Hyprpaper,
-                "swaybg" => WallpaperBackend::Swaybg,
-                "swww" => WallpaperBackend::Swww,
-                "wallutils" => WallpaperBackend::Wallutils,
-                "feh" => WallpaperBackend::Feh,
-                _ => return,
+                "none" => None,
+                id => match backend::find(id) {
+                    Some(backend) => Some(backend),
+                    None => return,
+                },
             };
             crate::set_wallpaper_backend(backend);
-            refresh_images(&flowbox_clone_backend, &image_loader_clone_backend);
+
+            let per_monitor = backend.is_some_and(|b| b.capabilities().per_monitor);
+            if !per_monitor {
+                monitor_combo_clone.set_active_id(Some(ALL_MONITORS));
+            }
+            monitor_combo_clone.set_visible(per_monitor);
+
+            refresh_images(&image_loader_clone_backend);
         }
     });
 
+    let profile_combo = ComboBoxText::new();
+    let config = config::load();
+    let profiles = config.profiles();
+    for name in &profiles {
+        profile_combo.append(Some(name), name);
+    }
+    if let Some(profile) = config.active_profile() {
+        profile_combo.set_active_id(Some(&profile.name));
+    }
+    profile_combo.set_visible(!profiles.is_empty());
+
+    let image_loader_clone_profile = Rc::clone(&image_loader);
+    let backend_combo_clone = backend_combo.clone();
+    let source_combo_clone = source_combo.clone();
+    profile_combo.connect_changed(move |combo| {
+        let Some(profile) = combo
+            .active_id()
+            .and_then(|name| config::load().profile(&name))
+        else {
+            return;
+        };
+
+        // Going through the backend combo stops the old backend and updates
+        // the monitor list the same way picking it by hand does.
+        if let Some(backend) = profile.backend {
+            backend_combo_clone.set_active_id(Some(backend.name()));
+        }
+
+        let image_loader = Rc::clone(&image_loader_clone_profile);
+        let source_combo = source_combo_clone.clone();
+        glib::spawn_future_local(async move {
+            match crate::use_profile(&profile.name).await {
+                Ok(_) => {
+                    if profile.folder.is_some() {
+                        fill_sources(&source_combo);
+                        load_images(&config::load().source_paths(), &image_loader);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error switching profile: {}", e);
+                    error_dialog("Error switching profile", &e);
+                }
+            }
+        });
+    });
+
     let search_button = Button::from_icon_name("system-search-symbolic");
     let search_entry = SearchEntry::new();
     search_entry.set_width_chars(25);
@@ -248,9 +677,10 @@ pub fn build_ui(app: &Application) {
     });
     search_entry.add_controller(key_controller);
 
-    let flowbox_clone = Rc::clone(&flowbox_ref);
+    let grid_filter_clone = Rc::clone(&grid_filter);
     search_entry.connect_changed(move |entry| {
-        filter_wallpapers(&flowbox_clone, entry.text());
+        grid_filter_clone.borrow_mut().search = entry.text().to_lowercase();
+        filter.changed(gtk::FilterChange::Different);
     });
 
     let left_box = GtkBox::new(gtk::Orientation::Horizontal, 5);
@@ -268,9 +698,15 @@ pub fn build_ui(app: &Application) {
     right_box.set_halign(gtk::Align::Center);
     right_box.set_hexpand(true);
     right_box.append(&choose_folder_button);
+    right_box.append(&add_folder_button);
+    right_box.append(&source_combo);
+    right_box.append(&remove_source_button);
+    right_box.append(&sort_combo);
     right_box.append(&refresh_button);
     right_box.append(&random_button);
+    right_box.append(&profile_combo);
     right_box.append(&backend_combo);
+    right_box.append(&monitor_combo);
     right_box.append(&exit_button);
 
     bottom_box.append(&left_box);
@@ -282,23 +718,20 @@ pub fn build_ui(app: &Application) {
 
     window.set_child(Some(&main_box));
 
-    let flowbox_clone_window = Rc::clone(&flowbox_ref);
     let image_loader_clone_window = Rc::clone(&image_loader);
     window.connect_show(move |_| {
-        if let Some(last_path) = load_last_path() {
-            let flowbox_clone2 = Rc::clone(&flowbox_clone_window);
+        let sources = config::load().source_paths();
+        if !sources.is_empty() {
             let image_loader_clone2 = Rc::clone(&image_loader_clone_window);
             glib::idle_add_local(move || {
-                load_images(&last_path, &flowbox_clone2, &image_loader_clone2);
+                load_images(&sources, &image_loader_clone2);
                 glib::ControlFlow::Break
             });
         }
     });
 
-    let flowbox_clone_random = Rc::clone(&flowbox_ref);
-    let image_loader_clone_random = Rc::clone(&image_loader);
     random_button.connect_clicked(move |_| {
-        set_random_wallpaper(&flowbox_clone_random, &image_loader_clone_random);
+        set_random_wallpaper(&filter_model);
     });
 
     let app_clone = app.clone();
@@ -311,11 +744,16 @@ pub fn build_ui(app: &Application) {
 
 fn choose_folder(
     window: &ApplicationWindow,
-    flowbox: &Rc<RefCell<FlowBox>>,
+    action: FolderAction,
+    source_combo: &ComboBoxText,
     image_loader: &Rc<RefCell<ImageLoader>>,
 ) {
+    let title = match action {
+        FolderAction::Change => "Change wallpaper folder",
+        FolderAction::Add => "Add wallpaper folder",
+    };
     let dialog = gtk::FileChooserDialog::new(
-        Some("Change wallpaper folder"),
+        Some(title),
         Some(window),
         gtk::FileChooserAction::SelectFolder,
         &[
@@ -324,17 +762,23 @@ fn choose_folder(
         ],
     );
 
-    if let Some(last_path) = load_last_path() {
+    if let Some(last_path) = config::load().folder() {
         let _ = dialog.set_current_folder(Some(&gio::File::for_path(last_path)));
     }
 
-    let flowbox_clone = Rc::clone(flowbox);
     let image_loader_clone = Rc::clone(image_loader);
+    let source_combo = source_combo.clone();
     dialog.connect_response(move |dialog, response| {
         if response == gtk::ResponseType::Accept {
             if let Some(folder) = dialog.file().and_then(|f| f.path()) {
-                load_images(&folder, &flowbox_clone, &image_loader_clone);
-                save_last_path(&folder);
+                config::update(|config| match action {
+                    FolderAction::Change => config.set_folder(&folder),
+                    FolderAction::Add => {
+                        config.add_source(&folder, None);
+                    }
+                });
+                fill_sources(&source_combo);
+                load_images(&config::load().source_paths(), &image_loader_clone);
             }
         }
         dialog.close();
@@ -343,371 +787,453 @@ fn choose_folder(
     dialog.show();
 }
 
-fn load_images(
-    folder: &Path,
-    flowbox: &Rc<RefCell<FlowBox>>,
-    image_loader: &Rc<RefCell<ImageLoader>>,
-) {
-    let mut image_loader = image_loader.borrow_mut();
-
-    if let Some(flag) = &image_loader.cancel_flag {
-        flag.store(true, Ordering::Relaxed);
-    }
-
-    image_loader.load_folder(folder);
-
-    let batch = image_loader.queue.drain(..).collect::<Vec<_>>();
-    let cache = Arc::clone(&image_loader.cache);
-    let backend_supports_gif = load_wallpaper_backend() == Some(WallpaperBackend::Swww);
-
-    let flowbox_clone = Rc::clone(flowbox);
-    let (sender, receiver) = unbounded::<(Texture, String)>();
-
-    while let Some(child) = flowbox.borrow().first_child() {
-        flowbox.borrow().remove(&child);
-    }
-
-    let cancel_flag = Arc::new(AtomicBool::new(false));
-    let cancel_flag_clone = Arc::clone(&cancel_flag);
-    let cancel_flag_clone2 = Arc::clone(&cancel_flag);
+// Walking and sniffing a large library takes seconds, so it happens on a
+// worker thread and the grid is filled when it's done.
+fn load_images(sources: &[PathBuf], image_loader: &Rc<RefCell<ImageLoader>>) {
+    // Animated wallpapers are left out when the backend can't show them
+    let formats = crate::wallpaper_formats();
+    let config = config::load();
+    let options = config.scan_options();
+    let key = config.sort();
+    let generation = image_loader
+        .borrow_mut()
+        .load_sources(sources, formats, options, key);
+
+    let sources = sources.to_vec();
+    let image_loader = Rc::clone(image_loader);
+    glib::spawn_future_local(async move {
+        let current = || image_loader.borrow().generation == generation;
+        let scanned = gio::spawn_blocking(move || {
+            let paths = scan::library(&sources, formats, &options);
+            (paths, Arc::new(sort::Ranker::new(key)))
+        })
+        .await;
 
-    std::thread::spawn(move || {
-        let num_cores = num_cpus::get();
-        batch
-            .par_iter()
-            .with_max_len(num_cores)
-            .for_each_with(sender.clone(), |s, path| {
-                if cancel_flag_clone.load(Ordering::Relaxed) {
+        if let Ok((paths, ranker)) = scanned {
+            if !current() {
+                return;
+            }
+            image_loader.borrow().clear();
+
+            // Ranking can mean reading every image header, so the grid fills in
+            // as batches are ranked instead of after the last one
+            for batch in paths.chunks(LOAD_BATCH) {
+                let batch = batch.to_vec();
+                let ranker = Arc::clone(&ranker);
+                let Ok(ranked) = gio::spawn_blocking(move || rank_all(&ranker, batch)).await else {
+                    break;
+                };
+                if !current() {
                     return;
                 }
+                image_loader.borrow().add(ranked);
+            }
+        }
 
-                let is_gif = path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map_or(false, |ext| ext.eq_ignore_ascii_case("gif"));
+        if current() {
+            image_loader.borrow_mut().loading = false;
+        }
+    });
+}
 
-                if is_gif && !backend_supports_gif {
-                    return;
-                }
+// Ranks what's shown by the new key in the background and lets the sort model
+// reorder it. A load still in progress is restarted with the new key instead.
+fn resort(image_loader: &Rc<RefCell<ImageLoader>>, key: sort::SortKey) {
+    let (loading, paths, generation) = {
+        let mut image_loader = image_loader.borrow_mut();
+        image_loader.key = key;
+        (
+            image_loader.loading,
+            image_loader.paths(),
+            image_loader.generation,
+        )
+    };
+    if loading {
+        refresh_images(image_loader);
+        return;
+    }
 
-                let texture = {
-                    let mut cache = cache.lock();
-                    match cache.get_or_insert(path, 250) {
-                        Some(texture) => texture,
-                        None => {
-                            eprintln!("Failed to load texture for {:?}", path);
-                            return;
-                        }
-                    }
-                };
+    let image_loader = Rc::clone(image_loader);
+    glib::spawn_future_local(async move {
+        let Ok(ranked) =
+            gio::spawn_blocking(move || rank_all(&sort::Ranker::new(key), paths)).await
+        else {
+            return;
+        };
 
-                let path_clone = path.to_str().unwrap_or("").to_string();
-                let path_with_tilde =
-                    path_clone.replace(&std::env::var("HOME").unwrap_or_default(), "~");
-                if s.send((texture, path_with_tilde)).is_err() {
-                    cancel_flag_clone.store(true, Ordering::Relaxed);
+        let image_loader = image_loader.borrow();
+        // Reloaded or sorted again meanwhile
+        if image_loader.generation != generation || image_loader.key != key {
+            return;
+        }
+        {
+            let mut ranks = image_loader.ranks.borrow_mut();
+            for (path, rank) in ranked {
+                // Skips wallpapers removed while ranking
+                if let Some(slot) = ranks.get_mut(&config::with_tilde(&path.to_string_lossy())) {
+                    *slot = rank;
                 }
-            });
-    });
-
-    glib::source::idle_add_local(move || {
-        if cancel_flag_clone2.load(Ordering::Relaxed) {
-            return ControlFlow::Break;
+            }
         }
+        image_loader.sorter.changed(gtk::SorterChange::Different);
+    });
+}
 
-        let flowbox = flowbox_clone.borrow_mut();
-        for _ in 0..10 {
-            match receiver.try_recv() {
-                Ok((texture, path_clone)) => {
-                    let image = Image::from_paintable(Some(&texture));
-                    image.set_pixel_size(250);
-
-                    let button = Button::builder().child(&image).build();
-                    button.set_has_frame(false);
+// Grid cells are recycled while scrolling. Each bind shows the cached
+// thumbnail or decodes it in the background, and the result is dropped if the
+// cell has moved on to another image by then.
+fn thumbnail_factory(image_loader: &ImageLoader) -> SignalListItemFactory {
+    let factory = SignalListItemFactory::new();
 
-                    let motion_controller = EventControllerMotion::new();
-                    let button_weak = button.downgrade();
-                    motion_controller.connect_enter(move |_, _, _| {
-                        if let Some(button) = button_weak.upgrade() {
-                            button.set_has_frame(true);
-                        }
-                    });
-                    let button_weak = button.downgrade();
-                    motion_controller.connect_leave(move |_| {
-                        if let Some(button) = button_weak.upgrade() {
-                            button.set_has_frame(false);
-                        }
-                    });
-                    button.add_controller(motion_controller);
-
-                    let gesture = gtk::GestureClick::new();
-                    gesture.set_button(3);
-                    let path_clone_preview = path_clone.clone();
-                    gesture.connect_released(move |gesture, _, _, _| {
-                        if let Some(widget) = gesture.widget() {
-                            show_preview_window(&path_clone_preview, &widget);
-                        }
-                    });
-                    button.add_controller(gesture);
+    let cache = Arc::clone(&image_loader.cache);
+    factory.connect_setup(move |_, item| {
+        let Some(item) = item.downcast_ref::<ListItem>() else {
+            return;
+        };
+        let image = Image::new();
+        image.set_pixel_size(THUMBNAIL_SIZE);
+
+        let gesture = gtk::GestureClick::new();
+        gesture.set_button(3);
+        let cache = Arc::clone(&cache);
+        let list_item = item.downgrade();
+        gesture.connect_released(move |gesture, _, _, _| {
+            let (Some(widget), Some(path)) = (gesture.widget(), bound_path(&list_item)) else {
+                return;
+            };
+            show_preview_window(&path, &widget, &cache);
+        });
+        image.add_controller(gesture);
 
-                    let file_name = Path::new(&path_clone)
-                        .file_name()
-                        .and_then(|name| name.to_str())
-                        .unwrap_or("Unknown");
-                    button.set_tooltip_text(Some(file_name));
+        item.set_child(Some(&image));
+    });
 
-                    let path_clone2 = path_clone.clone();
-                    button.connect_clicked(move |_| {
-                        crate::set_wallpaper(path_clone2.clone());
-                    });
+    let cache = Arc::clone(&image_loader.cache);
+    let visible = Arc::clone(&image_loader.visible);
+    factory.connect_bind(move |_, item| {
+        let Some(item) = item.downcast_ref::<ListItem>() else {
+            return;
+        };
+        let Some(image) = item.child().and_downcast::<Image>() else {
+            return;
+        };
+        let Some(path) = item.item().and_downcast::<StringObject>() else {
+            return;
+        };
+        let path = path.string();
+
+        let file_name = Path::new(path.as_str())
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("Unknown");
+        image.set_tooltip_text(Some(file_name));
+
+        let full_path = PathBuf::from(shellexpand::tilde(path.as_str()).into_owned());
+        let cached = cache.lock().get(&full_path, TextureKind::Thumbnail);
+        if let Some(texture) = cached {
+            image.set_paintable(Some(&texture));
+            return;
+        }
 
-                    flowbox.insert(&button, -1);
+        image.set_icon_name(Some("image-loading-symbolic"));
+        visible.lock().insert(full_path.clone());
+
+        let cache = Arc::clone(&cache);
+        let visible = Arc::clone(&visible);
+        let list_item = item.downgrade();
+        glib::spawn_future_local(async move {
+            let texture = gio::spawn_blocking(move || {
+                // Scrolled past before its turn came
+                if !visible.lock().contains(&full_path) {
+                    return None;
                 }
-                Err(crossbeam_channel::TryRecvError::Empty) => break,
-                Err(crossbeam_channel::TryRecvError::Disconnected) => {
-                    cancel_flag_clone2.store(true, Ordering::Relaxed);
-                    return ControlFlow::Break;
+                let texture = ImageCache::get_or_load(&cache, &full_path, THUMBNAIL_SIZE);
+                if texture.is_none() {
+                    eprintln!("Failed to load texture for {:?}", full_path);
                 }
+                texture
+            })
+            .await
+            .ok()
+            .flatten();
+
+            // The cell may have been recycled for another image meanwhile
+            if bound_path(&list_item).as_ref() != Some(&path) {
+                return;
+            }
+            match texture {
+                Some(texture) => image.set_paintable(Some(&texture)),
+                None => image.set_icon_name(Some("image-missing-symbolic")),
             }
+        });
+    });
+
+    let visible = Arc::clone(&image_loader.visible);
+    factory.connect_unbind(move |_, item| {
+        let Some(item) = item.downcast_ref::<ListItem>() else {
+            return;
+        };
+        if let Some(path) = item.item().and_downcast::<StringObject>() {
+            let path = shellexpand::tilde(path.string().as_str()).into_owned();
+            visible.lock().remove(Path::new(&path));
+        }
+        if let Some(image) = item.child().and_downcast::<Image>() {
+            image.clear();
         }
-        ControlFlow::Continue
     });
 
-    image_loader.cancel_flag = Some(cancel_flag);
+    factory
 }
 
-fn load_last_path() -> Option<PathBuf> {
-    let config_path = shellexpand::tilde("~/.config/hyprwall/config.ini").into_owned();
-    fs::File::open(config_path).ok().and_then(|mut file| {
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).ok()?;
-        contents
-            .lines()
-            .find(|line| line.starts_with("folder = "))
-            .map(|line| {
-                PathBuf::from(shellexpand::tilde(line.trim_start_matches("folder = ")).into_owned())
-            })
-    })
+// The wallpaper a grid cell currently shows, if it's still bound to one.
+fn bound_path(list_item: &glib::WeakRef<ListItem>) -> Option<glib::GString> {
+    list_item
+        .upgrade()?
+        .item()
+        .and_downcast::<StringObject>()
+        .map(|path| path.string())
 }
 
-pub fn save_last_path(path: &Path) {
-    let config_path = shellexpand::tilde(CONFIG_FILE).into_owned();
-    let mut contents = String::new();
-
-    if let Ok(mut file) = fs::File::open(&config_path) {
-        let _ = file.read_to_string(&mut contents);
-    }
-
-    let path_with_tilde = path
-        .to_string_lossy()
-        .replace(&std::env::var("HOME").unwrap_or_default(), "~");
-
-    if let Some(pos) = contents.find("folder = ") {
-        let end_pos = contents[pos..]
-            .find('\n')
-            .map(|p| p + pos)
-            .unwrap_or(contents.len());
-        contents.replace_range(pos..end_pos, &format!("folder = {}", path_with_tilde));
-    } else {
-        contents.push_str(&format!("folder = {}\n", path_with_tilde));
-    }
+// Applies what the folder watcher saw without rebuilding the whole grid.
+fn apply_changes(changes: watch::Changes, image_loader: &Rc<RefCell<ImageLoader>>) {
+    let tilde = |path: &PathBuf| config::with_tilde(&path.to_string_lossy());
+    let removed: HashSet<String> = changes.removed.iter().map(tilde).collect();
 
-    if let Ok(mut file) = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&config_path)
     {
-        let _ = file.write_all(contents.as_bytes());
-    }
-}
-
-fn set_random_wallpaper(_flowbox: &Rc<RefCell<FlowBox>>, image_loader: &Rc<RefCell<ImageLoader>>) {
-    let image_loader = image_loader.borrow();
-    if let Some(current_folder) = &image_loader.current_folder {
-        if let Ok(entries) = fs::read_dir(current_folder) {
-            let images: Vec<_> = entries
-                .filter_map(|entry| {
-                    entry.ok().and_then(|e| {
-                        let path = e.path();
-                        if path.is_file()
-                            && matches!(
-                                path.extension().and_then(|e| e.to_str()),
-                                Some("png" | "jpg" | "jpeg")
-                            )
-                        {
-                            Some(
-                                path.to_string_lossy()
-                                    .into_owned()
-                                    .replace(&std::env::var("HOME").unwrap_or_default(), "~"),
-                            )
-                        } else {
-                            None
-                        }
-                    })
-                })
-                .collect();
+        let image_loader = image_loader.borrow();
+        let mut cache = image_loader.cache.lock();
+        for path in &changes.modified {
+            cache.remove(path);
+        }
+    }
 
-            if let Some(random_image) = images.choose(&mut rand::thread_rng()) {
-                crate::set_wallpaper(random_image.to_string());
+    if !removed.is_empty() {
+        let image_loader = image_loader.borrow();
+        let store = &image_loader.store;
+        for position in (0..store.n_items()).rev() {
+            let Some(path) = store.item(position).and_downcast::<StringObject>() else {
+                continue;
+            };
+            if removed.contains(path.string().as_str()) {
+                store.remove(position);
             }
         }
+        let mut ranks = image_loader.ranks.borrow_mut();
+        for path in &removed {
+            ranks.remove(path);
+        }
     }
+
+    rank_changes(image_loader, changes.added, changes.modified);
 }
 
-pub fn custom_error_popup(title: &str, text: &str, modal: bool) {
-    let dialog = MessageDialog::builder()
-        .message_type(gtk::MessageType::Error)
-        .buttons(gtk::ButtonsType::Ok)
-        .title(title)
-        .text(text)
-        .modal(modal)
-        .build();
+// Added and modified wallpapers are ranked on a worker thread first, then
+// added or moved to their place.
+fn rank_changes(
+    image_loader: &Rc<RefCell<ImageLoader>>,
+    added: Vec<PathBuf>,
+    modified: Vec<PathBuf>,
+) {
+    if added.is_empty() && modified.is_empty() {
+        return;
+    }
+    let (generation, key) = {
+        let image_loader = image_loader.borrow();
+        (image_loader.generation, image_loader.key)
+    };
 
-    dialog.connect_response(|dialog, _| {
-        dialog.close();
+    let image_loader = Rc::clone(image_loader);
+    glib::spawn_future_local(async move {
+        let Ok((added, modified)) = gio::spawn_blocking(move || {
+            let ranker = sort::Ranker::new(key);
+            (rank_all(&ranker, added), rank_all(&ranker, modified))
+        })
+        .await
+        else {
+            return;
+        };
+
+        let loader = image_loader.borrow();
+        if loader.generation != generation {
+            return;
+        }
+        // The sort changed while ranking, rank again by the new key
+        if loader.key != key {
+            drop(loader);
+            let paths = |ranked: Vec<(PathBuf, Option<u64>)>| {
+                ranked.into_iter().map(|(path, _)| path).collect()
+            };
+            rank_changes(&image_loader, paths(added), paths(modified));
+            return;
+        }
+        loader.update(modified);
+        loader.add(added);
     });
+}
 
-    dialog.show();
+// Picks from the wallpapers the grid shows rather than scanning again, so the
+// folder filter and search apply.
+fn set_random_wallpaper(model: &FilterListModel) {
+    if model.n_items() == 0 {
+        return;
+    }
+    let position = rand::thread_rng().gen_range(0..model.n_items());
+    let path = model.item(position).and_downcast::<StringObject>();
+
+    if let Some(path) = path {
+        crate::set_wallpaper(path.string().into(), selected_monitor());
+    }
 }
 
-pub fn load_last_wallpaper() -> Option<String> {
-    let config_path = shellexpand::tilde(CONFIG_FILE).into_owned();
-    fs::File::open(config_path).ok().and_then(|mut file| {
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).ok()?;
-        contents
-            .lines()
-            .find(|line| line.starts_with("last_wallpaper = "))
-            .map(|line| line.trim_start_matches("last_wallpaper = ").to_string())
-    })
+fn selected_monitor() -> Option<String> {
+    SELECTED_MONITOR.lock().clone()
 }
 
-pub fn save_last_wallpaper(path: &str) {
-    let config_path = shellexpand::tilde(CONFIG_FILE).into_owned();
-    let mut contents = String::new();
+// A way out of an error that the dialog can take for the user.
+#[derive(Clone, Copy)]
+enum Remedy {
+    SwitchBackend,
+    AllMonitors,
+    OpenConfig,
+}
 
-    if let Ok(mut file) = fs::File::open(&config_path) {
-        let _ = file.read_to_string(&mut contents);
+impl Remedy {
+    fn for_error(error: &HyprwallError) -> Option<Self> {
+        match error {
+            HyprwallError::BackendNotInstalled { .. }
+            | HyprwallError::BackendFailedToStart { .. }
+            | HyprwallError::CommandFailed { .. } => Some(Remedy::SwitchBackend),
+            HyprwallError::NoMonitors => Some(Remedy::AllMonitors),
+            HyprwallError::ConfigParse(_) => Some(Remedy::OpenConfig),
+            HyprwallError::ImageUnreadable { .. } | HyprwallError::Other(_) => None,
+        }
     }
 
-    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
-    let wallpaper_line = format!("last_wallpaper = {}", path);
-
-    if let Some(pos) = lines
-        .iter()
-        .position(|line| line.starts_with("last_wallpaper = "))
-    {
-        lines[pos] = wallpaper_line;
-    } else {
-        lines.push(wallpaper_line);
+    fn label(self) -> &'static str {
+        match self {
+            Remedy::SwitchBackend => "Switch backend",
+            Remedy::AllMonitors => "Use all monitors",
+            Remedy::OpenConfig => "Open config file",
+        }
     }
 
-    let new_contents = lines.join("\n");
-
-    if let Ok(mut file) = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&config_path)
-    {
-        let _ = writeln!(file, "{}", new_contents);
+    fn apply(self) {
+        match self {
+            Remedy::SwitchBackend => activate_action(CHOOSE_BACKEND),
+            Remedy::AllMonitors => activate_action(ALL_MONITORS_ACTION),
+            Remedy::OpenConfig => {
+                let uri = gio::File::for_path(config::path()).uri();
+                if let Err(e) =
+                    gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>)
+                {
+                    eprintln!("Failed to open the config file: {}", e);
+                }
+            }
+        }
     }
 }
 
-pub fn save_wallpaper_backend(backend: &WallpaperBackend) {
-    let config_path = shellexpand::tilde(CONFIG_FILE).into_owned();
-    let mut contents = String::new();
-
-    if let Ok(mut file) = fs::File::open(&config_path) {
-        let _ = file.read_to_string(&mut contents);
+fn activate_action(name: &str) {
+    if let Some(app) = gio::Application::default() {
+        app.activate_action(name, None);
     }
+}
 
-    let backend_str = match backend {
-        WallpaperBackend::Hyprpaper => "hyprpaper",
-        WallpaperBackend::Swaybg => "swaybg",
-        WallpaperBackend::Swww => "swww",
-        WallpaperBackend::Wallutils => "wallutils",
-        WallpaperBackend::Feh => "feh",
-        WallpaperBackend::None => "none",
-    };
-
-    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
-    let backend_line = format!("backend = {}", backend_str);
+pub fn error_dialog(title: &str, error: &HyprwallError) {
+    let dialog = MessageDialog::builder()
+        .message_type(gtk::MessageType::Error)
+        .buttons(gtk::ButtonsType::Ok)
+        .title(title)
+        .text(error.to_string())
+        .modal(true)
+        .build();
+    dialog.set_secondary_text(error.hint().as_deref());
 
-    if let Some(pos) = lines.iter().position(|line| line.starts_with("backend = ")) {
-        lines[pos] = backend_line;
-    } else {
-        lines.push(backend_line);
+    let remedy = Remedy::for_error(error);
+    if let Some(remedy) = remedy {
+        dialog.add_button(remedy.label(), gtk::ResponseType::Accept);
     }
 
-    let new_contents = lines.join("\n");
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(remedy) = remedy {
+                remedy.apply();
+            }
+        }
+        dialog.close();
+    });
 
-    if let Ok(mut file) = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&config_path)
-    {
-        let _ = writeln!(file, "{}", new_contents);
-    }
-}
-
-pub fn load_wallpaper_backend() -> Option<WallpaperBackend> {
-    let config_path = shellexpand::tilde(CONFIG_FILE).into_owned();
-    fs::File::open(config_path).ok().and_then(|mut file| {
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).ok()?;
-        contents
-            .lines()
-            .find(|line| line.starts_with("backend = "))
-            .and_then(|line| {
-                let backend_str = line.trim_start_matches("backend = ");
-                match backend_str {
-                    "hyprpaper" => Some(WallpaperBackend::Hyprpaper),
-                    "swaybg" => Some(WallpaperBackend::Swaybg),
-                    "swww" => Some(WallpaperBackend::Swww),
-                    "wallutils" => Some(WallpaperBackend::Wallutils),
-                    "feh" => Some(WallpaperBackend::Feh),
-                    _ => None,
-                }
-            })
-    })
+    dialog.show();
 }
 
-fn refresh_images(flowbox: &Rc<RefCell<FlowBox>>, image_loader: &Rc<RefCell<ImageLoader>>) {
-    let current_folder = {
+fn refresh_images(image_loader: &Rc<RefCell<ImageLoader>>) {
+    let sources = {
         let image_loader = image_loader.borrow();
-        image_loader.current_folder.clone()
+        image_loader.sources.clone()
     };
 
-    if let Some(folder) = current_folder {
-        while let Some(child) = flowbox.borrow().first_child() {
-            flowbox.borrow().remove(&child);
-        }
-        load_images(&folder, flowbox, image_loader);
+    if !sources.is_empty() {
+        load_images(&sources, image_loader);
     }
 }
 
-fn filter_wallpapers(flowbox: &Rc<RefCell<FlowBox>>, search_text: impl AsRef<str>) {
-    let search_text = search_text.as_ref().to_lowercase();
-    let flowbox = flowbox.borrow();
+// Backends that are missing or don't fit the session stay listed but can't be
+// picked, and the tooltip says why.
+fn backend_combo() -> gtk::ComboBox {
+    let environment = backend::Environment::detect();
+    let model = gtk::ListStore::new(&[glib::Type::STRING, glib::Type::STRING, glib::Type::BOOL]);
+    model.set(&model.append(), &[(0, &"none"), (1, &"None"), (2, &true)]);
+
+    let mut reasons = Vec::new();
+    for backend in backend::BACKENDS {
+        let availability = backend::availability(*backend, &environment);
+        let available = availability.is_available();
+        let label = if available {
+            backend.display_name().to_string()
+        } else {
+            reasons.push(format!("{}: {}", backend.display_name(), availability));
+            format!("{} (unavailable)", backend.display_name())
+        };
+        model.set(
+            &model.append(),
+            &[(0, &backend.name()), (1, &label), (2, &available)],
+        );
+    }
 
-    let filter = move |child: &FlowBoxChild| {
-        if search_text.is_empty() {
-            return true;
-        }
+    let combo = gtk::ComboBox::with_model(&model);
+    combo.set_id_column(0);
+    let cell = gtk::CellRendererText::new();
+    combo.pack_start(&cell, true);
+    combo.add_attribute(&cell, "text", 1);
+    combo.add_attribute(&cell, "sensitive", 2);
+    if !reasons.is_empty() {
+        combo.set_tooltip_text(Some(&reasons.join("\n")));
+    }
+    combo
+}
 
-        if let Some(button) = child.child().and_downcast::<Button>() {
-            if let Some(tooltip) = button.tooltip_text() {
-                return tooltip.to_lowercase().contains(&search_text);
-            }
-        }
-        false
-    };
+fn fill_sources(combo: &ComboBoxText) {
+    let sources = config::load().sources();
 
-    flowbox.set_filter_func(Box::new(filter));
+    combo.remove_all();
+    combo.append(Some(ALL_SOURCES), "All folders");
+    for source in &sources {
+        combo.append(
+            Some(&config::with_tilde(&source.path.to_string_lossy())),
+            &source.name,
+        );
+    }
+    combo.set_active_id(Some(ALL_SOURCES));
+    combo.set_visible(sources.len() > 1);
 }
 
-fn show_preview_window(path: &str, parent_widget: &impl IsA<gtk::Widget>) {
+fn show_preview_window(
+    path: &str,
+    parent_widget: &impl IsA<gtk::Widget>,
+    cache: &Arc<Mutex<ImageCache>>,
+) {
     let path = shellexpand::tilde(path).into_owned();
+    let cache = Arc::clone(cache);
     let parent = parent_widget.root().and_downcast::<gtk::Window>();
 
     glib::spawn_future_local(async move {
@@ -744,17 +1270,28 @@ fn show_preview_window(path: &str, parent_widget: &impl IsA<gtk::Widget>) {
 
         let (sender, receiver) = crossbeam_channel::unbounded::<Result<Texture, String>>();
 
-        std::thread::spawn(move || {
-            let file = gio::File::for_path(&path_buf);
-            match Texture::from_file(&file) {
-                Ok(texture) => {
-                    let _ = sender.send(Ok(texture));
-                }
-                Err(e) => {
-                    let _ = sender.send(Err(e.to_string()));
-                }
+        let cached = cache.lock().get(&path_buf, TextureKind::Preview);
+        match cached {
+            Some(texture) => {
+                let _ = sender.send(Ok(texture));
             }
-        });
+            None => {
+                std::thread::spawn(move || {
+                    let file = gio::File::for_path(&path_buf);
+                    match Texture::from_file(&file) {
+                        Ok(texture) => {
+                            cache
+                                .lock()
+                                .insert(path_buf, TextureKind::Preview, texture.clone());
+                            let _ = sender.send(Ok(texture));
+                        }
+                        Err(e) => {
+                            let _ = sender.send(Err(e.to_string()));
+                        }
+                    }
+                });
+            }
+        }
 
         glib::source::idle_add_local(move || match receiver.try_recv() {
             Ok(result) => {
@@ -785,3 +1322,180 @@ fn show_preview_window(path: &str, parent_widget: &impl IsA<gtk::Widget>) {
         });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    const FIXTURE_IMAGES: usize = 1000;
+    const FIXTURE_SIZE: i32 = 512;
+
+    fn texture() -> Texture {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, 4, 4).unwrap();
+        Texture::for_pixbuf(&pixbuf)
+    }
+
+    #[test]
+    fn concurrent_loads_of_one_image_decode_once() {
+        let cache = Mutex::new(ImageCache::new(usize::MAX));
+        let decodes = AtomicUsize::new(0);
+        let path = Path::new("/wallpapers/one.png");
+
+        let textures: Vec<Option<Texture>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        ImageCache::get_or_decode(&cache, path, || {
+                            decodes.fetch_add(1, Ordering::SeqCst);
+                            // Long enough for every other thread to find the decode pending
+                            std::thread::sleep(Duration::from_millis(200));
+                            Some(texture())
+                        })
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+
+        assert_eq!(decodes.load(Ordering::SeqCst), 1);
+        let first = textures[0].clone().expect("the decode returned a texture");
+        assert!(textures.iter().all(|t| t.as_ref() == Some(&first)));
+        assert!(cache.lock().loading.is_empty());
+    }
+
+    #[test]
+    fn failed_decodes_are_shared_but_not_cached() {
+        let cache = Mutex::new(ImageCache::new(usize::MAX));
+        let decodes = AtomicUsize::new(0);
+        let path = Path::new("/wallpapers/broken.png");
+        let decode = || {
+            decodes.fetch_add(1, Ordering::SeqCst);
+            None
+        };
+
+        assert!(ImageCache::get_or_decode(&cache, path, decode).is_none());
+        assert!(ImageCache::get_or_decode(&cache, path, decode).is_none());
+        assert_eq!(decodes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn panicking_decodes_release_their_waiters() {
+        let cache = Mutex::new(ImageCache::new(usize::MAX));
+        let path = Path::new("/wallpapers/panics.png");
+
+        std::thread::scope(|scope| {
+            let decoder = scope.spawn(|| {
+                ImageCache::get_or_decode(&cache, path, || {
+                    std::thread::sleep(Duration::from_millis(200));
+                    panic!("a broken loader");
+                })
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            let waiter = scope.spawn(|| {
+                ImageCache::get_or_decode(&cache, path, || unreachable!("already decoding"))
+            });
+
+            assert!(decoder.join().is_err());
+            assert!(waiter.join().unwrap().is_none());
+        });
+        assert!(cache.lock().loading.is_empty());
+        assert!(cache.lock().cache.is_empty());
+    }
+
+    #[test]
+    fn least_recently_used_textures_are_evicted_first() {
+        // Room for two 4x4 RGBA textures
+        let mut cache = ImageCache::new(2 * 4 * 4 * 4);
+        let (a, b, c) = (Path::new("/a"), Path::new("/b"), Path::new("/c"));
+        cache.insert(a.to_path_buf(), TextureKind::Thumbnail, texture());
+        cache.insert(b.to_path_buf(), TextureKind::Thumbnail, texture());
+
+        assert!(cache.get(a, TextureKind::Thumbnail).is_some());
+        cache.insert(c.to_path_buf(), TextureKind::Thumbnail, texture());
+
+        assert!(cache.get(a, TextureKind::Thumbnail).is_some());
+        assert!(cache.get(b, TextureKind::Thumbnail).is_none());
+        assert!(cache.get(c, TextureKind::Thumbnail).is_some());
+        assert_eq!(cache.bytes, 2 * 4 * 4 * 4);
+        assert_eq!(cache.order.len(), cache.cache.len());
+
+        cache.remove(a);
+        assert!(cache.get(a, TextureKind::Thumbnail).is_none());
+        assert_eq!(cache.bytes, 4 * 4 * 4);
+        assert_eq!(cache.order.len(), 1);
+    }
+
+    fn write_fixture(dir: &TempDir) -> Vec<PathBuf> {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, FIXTURE_SIZE, FIXTURE_SIZE).unwrap();
+        (0..FIXTURE_IMAGES)
+            .map(|i| {
+                pixbuf.fill(((i as u32) << 8) | 0xff);
+                let path = dir.join(format!("{}.png", i));
+                pixbuf.savev(&path, "png", &[]).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn decode(path: &Path) -> Option<Texture> {
+        Pixbuf::from_file_at_scale(path, THUMBNAIL_SIZE, THUMBNAIL_SIZE, true)
+            .ok()
+            .map(|pixbuf| Texture::for_pixbuf(&pixbuf))
+    }
+
+    // How thumbnails were loaded before, decoding with the cache locked.
+    fn load_under_lock(cache: &Mutex<ImageCache>, path: &Path) -> Option<Texture> {
+        let mut cache = cache.lock();
+        if let Some(texture) = cache.get(path, TextureKind::Thumbnail) {
+            return Some(texture);
+        }
+        let texture = decode(path)?;
+        cache.insert(path.to_path_buf(), TextureKind::Thumbnail, texture.clone());
+        Some(texture)
+    }
+
+    fn time_threads(paths: &[PathBuf], load: impl Fn(&Path) -> Option<Texture> + Sync) -> Duration {
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for chunk in paths.chunks(paths.len().div_ceil(threads)) {
+                let load = &load;
+                scope.spawn(move || {
+                    for path in chunk {
+                        assert!(load(path).is_some());
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    // cargo test --release -- --ignored --nocapture thumbnail_decode_benchmark
+    #[test]
+    #[ignore = "benchmark, writes and decodes a 1000 image fixture"]
+    fn thumbnail_decode_benchmark() {
+        let dir = TempDir::new("decode-benchmark");
+        let paths = write_fixture(&dir);
+
+        let locked_cache = Mutex::new(ImageCache::new(usize::MAX));
+        let locked = time_threads(&paths, |path| load_under_lock(&locked_cache, path));
+
+        let cache = Mutex::new(ImageCache::new(usize::MAX));
+        let parallel = time_threads(&paths, |path| {
+            ImageCache::get_or_decode(&cache, path, || decode(path))
+        });
+
+        println!(
+            "{} images: {:?} decoding under the lock, {:?} with get_or_decode ({:.1}x)",
+            paths.len(),
+            locked,
+            parallel,
+            locked.as_secs_f64() / parallel.as_secs_f64()
+        );
+        assert_eq!(cache.lock().cache.len(), FIXTURE_IMAGES);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+pub type Reply = Result<String, String>;
+pub type RequestSender = mpsc::Sender<(Request, oneshot::Sender<Reply>)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Next,
+    Prev,
+    Pause,
+    Resume,
+    Set(String),
+    Status,
+    Reload,
+}
+
+impl Request {
+    fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+        match (command, argument) {
+            ("next", "") => Ok(Request::Next),
+            ("prev", "") => Ok(Request::Prev),
+            ("pause", "") => Ok(Request::Pause),
+            ("resume", "") => Ok(Request::Resume),
+            ("set", path) if !path.is_empty() => Ok(Request::Set(path.to_string())),
+            ("status", "") => Ok(Request::Status),
+            ("reload", "") => Ok(Request::Reload),
+            _ => Err(format!("Unknown command: {}", line)),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Next => write!(f, "next"),
+            Request::Prev => write!(f, "prev"),
+            Request::Pause => write!(f, "pause"),
+            Request::Resume => write!(f, "resume"),
+            Request::Set(path) => write!(f, "set {}", path),
+            Request::Status => write!(f, "status"),
+            Request::Reload => write!(f, "reload"),
+        }
+    }
+}
+
+// The temp dir is shared between users, so outside XDG_RUNTIME_DIR the socket
+// is named after the user and each one's daemon keeps to its own.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("hyprwall.sock"),
+        None => std::env::temp_dir().join(format!("hyprwall-{}.sock", user_id())),
+    }
+}
+
+// /proc/self belongs to whoever runs the process.
+fn user_id() -> String {
+    std::fs::metadata("/proc/self")
+        .map(|metadata| metadata.uid().to_string())
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default()
+}
+
+pub async fn bind() -> Result<UnixListener, String> {
+    let path = socket_path();
+
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(format!(
+                "Another hyprwall daemon is already listening on {}",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove stale socket {}: {}", path.display(), e))?;
+    }
+
+    UnixListener::bind(&path)
+        .map_err(|e| format!("Failed to bind socket {}: {}", path.display(), e))
+}
+
+pub fn remove_socket() {
+    let _ = std::fs::remove_file(socket_path());
+}
+
+pub async fn serve(listener: UnixListener, sender: RequestSender) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept IPC connection: {}", e);
+                continue;
+            }
+        };
+
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, sender).await {
+                eprintln!("IPC client error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_client(stream: UnixStream, sender: RequestSender) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let reply = match Request::parse(&line) {
+        Ok(request) => {
+            let (reply_sender, reply_receiver) = oneshot::channel();
+            if sender.send((request, reply_sender)).await.is_err() {
+                Err("Daemon is shutting down".to_string())
+            } else {
+                reply_receiver
+                    .await
+                    .unwrap_or_else(|_| Err("Daemon did not reply".to_string()))
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let text = match reply {
+        Ok(message) => message,
+        Err(e) => format!("error: {}", e),
+    };
+    writer.write_all(text.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.shutdown().await
+}
+
+pub async fn send(request: &Request) -> Result<String, String> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path).await.map_err(|e| {
+        format!(
+            "Failed to connect to {} (is `hyprwall daemon` running?): {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .await
+        .map_err(|e| format!("Failed to read reply: {}", e))?;
+
+    let reply = reply.trim_end();
+    match reply.strip_prefix("error: ") {
+        Some(e) => Err(e.to_string()),
+        None => Ok(reply.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::Next,
+            Request::Prev,
+            Request::Pause,
+            Request::Resume,
+            Request::Set("/walls/a.png".to_string()),
+            Request::Status,
+            Request::Reload,
+        ];
+        for request in requests {
+            assert_eq!(Request::parse(&format!("{}\n", request)), Ok(request));
+        }
+    }
+
+    #[test]
+    fn paths_keep_their_spaces() {
+        let request = Request::Set("/my walls/sunset  at sea.png".to_string());
+        assert_eq!(request.to_string(), "set /my walls/sunset  at sea.png");
+        assert_eq!(Request::parse(&format!("{}\r\n", request)), Ok(request));
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        assert_eq!(
+            Request::parse("shuffle\n"),
+            Err("Unknown command: shuffle".to_string())
+        );
+        assert_eq!(
+            Request::parse("set\n"),
+            Err("Unknown command: set".to_string())
+        );
+        assert!(Request::parse("set \n").is_err());
+        assert!(Request::parse("next please\n").is_err());
+        assert!(Request::parse("").is_err());
+    }
+}
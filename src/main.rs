@@ -1,36 +1,47 @@
+mod backend;
+mod config;
+mod convert;
+mod daemon;
+mod doctor;
+mod error;
 mod gui;
-
-use clap::Parser;
+mod ipc;
+mod monitor;
+mod scan;
+mod schedule;
+mod solar;
+mod sort;
+#[cfg(test)]
+mod testing;
+mod thumbnail;
+mod watch;
+
+use backend::{Assignment, Backend};
+use clap::{Parser, Subcommand};
+use config::Config;
+use error::HyprwallError;
 use gtk::{prelude::*, Application};
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use rand::seq::SliceRandom;
-use shellexpand::tilde;
-use std::fs::{self, OpenOptions};
-use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::process::Command as TokioCommand;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
 
 lazy_static! {
-    static ref MONITORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
-    static ref CURRENT_BACKEND: Mutex<WallpaperBackend> = Mutex::new(WallpaperBackend::None);
+    static ref CURRENT_BACKEND: Mutex<Option<&'static dyn Backend>> = Mutex::new(None);
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum WallpaperBackend {
-    None,
-    Hyprpaper,
-    Swaybg,
-    Swww,
-    Wallutils,
-    Feh,
-}
+// Set while CURRENT_BACKEND was picked for the session rather than configured.
+static BACKEND_PICKED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     #[arg(short = 'r', long, help = "Restore the last selected wallpaper")]
     restore: bool,
 
@@ -46,6 +57,22 @@ struct Cli {
     #[arg(short = 'w', long, help = "Set a specific wallpaper", default_value = None)]
     wallpaper: Option<PathBuf>,
 
+    #[arg(
+        short = 'm',
+        long,
+        help = "Only set the wallpaper on the given monitor (used with -w or -R)",
+        default_value = None
+    )]
+    monitor: Option<String>,
+
+    #[arg(
+        short = 'c',
+        long,
+        help = "Use the given config file instead of the default location",
+        default_value = None
+    )]
+    config: Option<PathBuf>,
+
     #[arg(short = 'g', long, help = "Generate the config file")]
     generate: bool,
 
@@ -60,37 +87,161 @@ struct Cli {
     copyright: bool,
 }
 
+#[derive(Subcommand)]
+enum Commands {
+    #[command(about = "Stay resident and rotate wallpapers from the wallpaper folder")]
+    Daemon {
+        #[arg(
+            short = 'i',
+            long,
+            help = "Time between wallpaper changes, e.g. 30s, 15m or 1h30m (default: the profile's interval or 15m)",
+            value_parser = daemon::parse_interval
+        )]
+        interval: Option<Duration>,
+    },
+
+    #[command(about = "List, add or remove wallpaper folders")]
+    Source {
+        #[command(subcommand)]
+        command: SourceCommand,
+    },
+
+    #[command(about = "List or switch named profiles")]
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+
+    #[command(about = "List the wallpapers in the library")]
+    List {
+        #[arg(
+            short = 's',
+            long,
+            help = "Order by name, modified, size, resolution or last_used (default: the configured sort)",
+            value_parser = sort::SortKey::parse
+        )]
+        sort: Option<sort::SortKey>,
+    },
+
+    #[command(about = "Manage hyprwall's thumbnail and conversion cache")]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+
+    #[command(about = "Show which scheduled wallpaper is active")]
+    Schedule {
+        #[arg(
+            short = 'e',
+            long,
+            help = "List every entry and when the next change happens"
+        )]
+        explain: bool,
+
+        #[arg(
+            short = 'a',
+            long,
+            help = "Check the schedule at the given time (HH:MM) instead of now",
+            value_parser = schedule::parse_time
+        )]
+        at: Option<chrono::NaiveTime>,
+    },
+
+    #[command(about = "Check the session, backends, config, folders and monitors for problems")]
+    Doctor,
+
+    #[command(about = "Tell the running daemon to show the next wallpaper")]
+    Next,
+
+    #[command(about = "Tell the running daemon to go back to the previous wallpaper")]
+    Prev,
+
+    #[command(about = "Pause the running daemon's rotation")]
+    Pause,
+
+    #[command(about = "Resume the running daemon's rotation")]
+    Resume,
+
+    #[command(about = "Tell the running daemon to show a specific wallpaper")]
+    Set { path: PathBuf },
+
+    #[command(about = "Show the running daemon's state")]
+    Status,
+
+    #[command(about = "Make the running daemon reread the config and wallpaper folder")]
+    Reload,
+}
+
+#[derive(Subcommand)]
+enum SourceCommand {
+    #[command(about = "List the wallpaper folders in the config")]
+    List,
+
+    #[command(about = "Add a folder to the wallpaper library")]
+    Add {
+        path: PathBuf,
+
+        #[arg(short = 'n', long, help = "Name shown in the GUI folder filter")]
+        name: Option<String>,
+    },
+
+    #[command(about = "Remove a folder by name or path")]
+    Remove { source: String },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    #[command(about = "List the profiles in the config")]
+    List,
+
+    #[command(about = "Switch to a profile and apply its wallpapers")]
+    Use { name: String },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    #[command(about = "Remove thumbnails of changed or deleted images and old converted copies")]
+    Prune,
+}
+
+impl Commands {
+    fn request(&self) -> Option<ipc::Request> {
+        match self {
+            Commands::Daemon { .. }
+            | Commands::Schedule { .. }
+            | Commands::Source { .. }
+            | Commands::Profile { .. }
+            | Commands::List { .. }
+            | Commands::Cache { .. }
+            | Commands::Doctor => None,
+            Commands::Next => Some(ipc::Request::Next),
+            Commands::Prev => Some(ipc::Request::Prev),
+            Commands::Pause => Some(ipc::Request::Pause),
+            Commands::Resume => Some(ipc::Request::Resume),
+            Commands::Set { path } => Some(ipc::Request::Set(
+                config::absolute_path(path.clone())
+                    .to_string_lossy()
+                    .into_owned(),
+            )),
+            Commands::Status => Some(ipc::Request::Status),
+            Commands::Reload => Some(ipc::Request::Reload),
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
 
     let cli = Cli {
-        wallpaper: cli
-            .wallpaper
-            .map(|p| {
-                if p.is_relative() {
-                    std::env::current_dir()
-                        .map(|cur| cur.join(p.clone()))
-                        .unwrap_or(p)
-                } else {
-                    p
-                }
-            })
-            .map(|p| PathBuf::from(shellexpand::tilde(&p.to_string_lossy()).into_owned())),
-        folder: cli
-            .folder
-            .map(|p| {
-                if p.is_relative() {
-                    std::env::current_dir()
-                        .map(|cur| cur.join(p.clone()))
-                        .unwrap_or(p)
-                } else {
-                    p
-                }
-            })
-            .map(|p| PathBuf::from(shellexpand::tilde(&p.to_string_lossy()).into_owned())),
+        wallpaper: cli.wallpaper.map(config::absolute_path),
+        folder: cli.folder.map(config::absolute_path),
         ..cli
     };
 
+    if let Some(path) = &cli.config {
+        config::set_override(config::absolute_path(path.clone()));
+    }
+
     let rt = Runtime::new().expect("Failed to create Tokio runtime");
     let _guard = rt.enter();
 
@@ -130,6 +281,37 @@ along with this program; if not, see
         return;
     }
 
+    if let Some(request) = cli.command.as_ref().and_then(Commands::request) {
+        match rt.block_on(ipc::send(&request)) {
+            Ok(reply) => println!("{}", reply),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Commands::Cache { command }) = &cli.command {
+        match command {
+            CacheCommand::Prune => prune_cache(),
+        }
+        return;
+    }
+
+    if let Some(Commands::Schedule { explain, at }) = cli.command {
+        show_schedule(explain, at);
+        return;
+    }
+
+    // Runs before the config is generated, so it reports what is really there
+    if let Some(Commands::Doctor) = cli.command {
+        if rt.block_on(doctor::run()) > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if !config_exists() {
         generate_config();
     }
@@ -144,29 +326,60 @@ along with this program; if not, see
         set_folder(&folder);
     }
 
+    if let Some(Commands::Source { command }) = &cli.command {
+        match command {
+            SourceCommand::List => list_sources(),
+            SourceCommand::Add { path, name } => add_source(path, name.as_deref()),
+            SourceCommand::Remove { source } => remove_source(source),
+        }
+        return;
+    }
+
+    if let Some(Commands::List { sort }) = cli.command {
+        if let Err(e) = rt.block_on(list_library(sort)) {
+            fail("listing wallpapers", &e);
+        }
+        return;
+    }
+
+    if let Some(Commands::Profile { command }) = &cli.command {
+        match command {
+            ProfileCommand::List => list_profiles(),
+            ProfileCommand::Use { name } => {
+                if let Err(e) = rt.block_on(use_profile(name)) {
+                    fail("switching profile", &e);
+                }
+            }
+        }
+        return;
+    }
+
+    if let Some(Commands::Daemon { interval }) = cli.command {
+        if let Err(e) = rt.block_on(daemon::run(interval, cli.monitor)) {
+            fail("running daemon", &e);
+        }
+        return;
+    }
+
     if let Some(wallpaper) = &cli.wallpaper {
-        let wallpaper_path = wallpaper
-            .to_string_lossy()
-            .replace(&std::env::var("HOME").unwrap_or_default(), "~");
+        let wallpaper_path = config::with_tilde(&wallpaper.to_string_lossy());
 
         let rt = Runtime::new().expect("Failed to create Tokio runtime");
         rt.block_on(async {
-            let current_backend = *CURRENT_BACKEND.lock();
-            if current_backend == WallpaperBackend::None {
+            let Some(previous_backend) = *CURRENT_BACKEND.lock() else {
                 eprintln!("No wallpaper backend set. Please set a backend using the -b or --backend option.");
-                return;
-            }
+                std::process::exit(1);
+            };
 
-            let previous_backend = current_backend;
-            drop_all_wallpapers(previous_backend).await;
-            kill_previous_backend(previous_backend).await;
+            previous_backend.clear().await;
+            previous_backend.stop().await;
 
-            match set_wallpaper_internal(&wallpaper_path).await {
+            match set_wallpaper_internal(&wallpaper_path, cli.monitor.as_deref()).await {
                 Ok(_) => {
                     println!("Wallpaper set successfully: {}", wallpaper_path);
-                    gui::save_last_wallpaper(&wallpaper_path);
+                    remember_wallpaper(&wallpaper_path, cli.monitor.as_deref());
                 }
-                Err(e) => eprintln!("Error setting wallpaper: {}", e),
+                Err(e) => fail("setting wallpaper", &e),
             }
         });
         return;
@@ -178,7 +391,7 @@ along with this program; if not, see
     }
 
     if cli.random {
-        set_random_wallpaper();
+        set_random_wallpaper(cli.monitor.as_deref());
         return;
     }
 
@@ -187,43 +400,51 @@ along with this program; if not, see
         .build();
 
     app.connect_activate(gui::build_ui);
-    app.run();
+    // Our flags were handled by clap, GTK would reject them as unknown options.
+    app.run_with_args::<&str>(&[]);
+}
+
+// Exits with the code for the kind of failure, so scripts can tell a missing
+// backend from a broken config.
+fn fail(context: &str, error: &HyprwallError) -> ! {
+    eprintln!("Error {}: {}", context, error);
+    if let Some(hint) = error.hint() {
+        eprintln!("Hint: {}", hint);
+    }
+    std::process::exit(error.exit_code());
 }
 
 fn config_exists() -> bool {
-    let config_path = tilde("~/.config/hyprwall/config.ini").into_owned();
-    Path::new(&config_path).exists()
+    config::path().exists()
 }
 
 fn generate_config() {
-    let config_path = tilde("~/.config/hyprwall/config.ini").into_owned();
-    let config_dir = Path::new(&config_path).parent().unwrap();
-    std::fs::create_dir_all(config_dir).expect("Failed to create config directory");
-
-    let default_config = r#"[Settings]
-folder = none
-backend = none
-last_wallpaper = none
-"#;
-
-    std::fs::write(&config_path, default_config).expect("Failed to write config file");
-    println!("Config file generated at: {}", config_path);
-}
-
-fn set_backend(backend: &str) {
-    let backend = match backend.to_lowercase().as_str() {
-        "hyprpaper" => WallpaperBackend::Hyprpaper,
-        "swaybg" => WallpaperBackend::Swaybg,
-        "swww" => WallpaperBackend::Swww,
-        "wallutils" => WallpaperBackend::Wallutils,
-        "feh" => WallpaperBackend::Feh,
-        _ => {
-            eprintln!("Invalid backend specified. Using default (Hyprpaper).");
-            WallpaperBackend::Hyprpaper
-        }
+    config::write_default().expect("Failed to write config file");
+    println!("Config file generated at: {}", config::path().display());
+}
+
+fn set_backend(name: &str) {
+    let Some(backend) = backend::find(name) else {
+        let names: Vec<&str> = backend::BACKENDS.iter().map(|b| b.name()).collect();
+        eprintln!(
+            "Unknown backend {}, expected one of {}",
+            name,
+            names.join(", ")
+        );
+        std::process::exit(1);
     };
-    set_wallpaper_backend(backend);
-    println!("Wallpaper backend set to: {:?}", backend);
+
+    let availability = backend::availability(backend, &backend::Environment::detect());
+    if !availability.is_available() {
+        eprintln!(
+            "Warning: {} may not work, {}",
+            backend.display_name(),
+            availability
+        );
+    }
+
+    set_wallpaper_backend(Some(backend));
+    println!("Wallpaper backend set to: {}", backend.display_name());
 }
 
 fn set_folder(folder: &Path) {
@@ -232,391 +453,472 @@ fn set_folder(folder: &Path) {
     let folder_path = Path::new(&folder_expanded);
 
     if folder_path.is_dir() {
-        let config_path = tilde("~/.config/hyprwall/config.ini").into_owned();
-        let mut contents = String::new();
+        config::update(|config| config.set_folder(folder_path));
+        println!(
+            "Wallpaper folder set to: {}",
+            config::with_tilde(&folder_expanded)
+        );
+    } else {
+        eprintln!("Specified folder does not exist or is not a directory.");
+    }
+}
 
-        if let Ok(mut file) = fs::File::open(&config_path) {
-            file.read_to_string(&mut contents).unwrap_or_default();
-        }
+fn prune_cache() {
+    let pruned = thumbnail::prune();
+    println!(
+        "Removed {} stale thumbnails and {} converted wallpapers",
+        pruned.thumbnails, pruned.converted
+    );
+}
 
-        let mut lines: Vec<String> = contents.lines().map(String::from).collect();
-        let folder_with_tilde = folder_str.replace(&std::env::var("HOME").unwrap_or_default(), "~");
-        let folder_line = format!("folder = {}", folder_with_tilde);
+fn list_sources() {
+    let sources = config::load().sources();
+    if sources.is_empty() {
+        println!("No wallpaper folders in the config");
+    }
+    for source in sources {
+        println!(
+            "{}: {}",
+            source.name,
+            config::with_tilde(&source.path.to_string_lossy())
+        );
+    }
+}
 
-        if let Some(pos) = lines.iter().position(|line| line.starts_with("folder = ")) {
-            lines[pos] = folder_line;
-        } else {
-            lines.push(folder_line);
-        }
+fn add_source(path: &Path, name: Option<&str>) {
+    let path = config::absolute_path(path.to_path_buf());
+    if !path.is_dir() {
+        eprintln!("Specified folder does not exist or is not a directory.");
+        std::process::exit(1);
+    }
 
-        let new_contents = lines.join("\n");
+    let mut added = None;
+    config::update(|config| added = Some(config.add_source(&path, name)));
+    if let Some(name) = added {
+        println!(
+            "Wallpaper folder {} added as {}",
+            config::with_tilde(&path.to_string_lossy()),
+            name
+        );
+    }
+}
 
-        if let Ok(mut file) = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&config_path)
-        {
-            let _ = writeln!(file, "{}", new_contents);
+fn remove_source(source: &str) {
+    let mut removed = None;
+    config::update(|config| removed = config.remove_source(source));
+    match removed {
+        Some(source) => println!(
+            "Wallpaper folder {} removed",
+            config::with_tilde(&source.path.to_string_lossy())
+        ),
+        None => {
+            eprintln!("No wallpaper folder named {} in the config", source);
+            std::process::exit(1);
         }
-
-        println!("Wallpaper folder set to: {}", folder_with_tilde);
-    } else {
-        eprintln!("Specified folder does not exist or is not a directory.");
     }
 }
 
-fn set_random_wallpaper() {
+fn set_random_wallpaper(monitor: Option<&str>) {
     let rt = Runtime::new().expect("Failed to create Tokio runtime");
     rt.block_on(async {
         match get_random_wallpaper().await {
-            Ok(path) => match set_wallpaper_internal(&path).await {
+            Ok(path) => match set_wallpaper_internal(&path, monitor).await {
                 Ok(_) => {
                     println!("Random wallpaper set successfully: {}", path);
-                    gui::save_last_wallpaper(&path);
+                    remember_wallpaper(&path, monitor);
                 }
-                Err(e) => eprintln!("Error setting random wallpaper: {}", e),
+                Err(e) => fail("setting random wallpaper", &e),
             },
-            Err(e) => eprintln!("Error getting random wallpaper: {}", e),
+            Err(e) => fail("getting random wallpaper", &e),
         }
     });
 }
 
-async fn get_random_wallpaper() -> Result<String, String> {
-    let config_path = shellexpand::tilde("~/.config/hyprwall/config.ini").into_owned();
-    let contents = tokio::fs::read_to_string(&config_path)
-        .await
-        .map_err(|e| format!("Failed to read config file: {}", e))?;
-
-    let folder = contents
-        .lines()
-        .find(|line| line.starts_with("folder = "))
-        .map(|line| line.trim_start_matches("folder = "))
-        .ok_or_else(|| "Wallpaper folder not found in config".to_string())?;
-
-    let folder_path = PathBuf::from(shellexpand::tilde(folder).into_owned());
-
-    let mut entries = tokio::fs::read_dir(&folder_path)
-        .await
-        .map_err(|e| format!("Failed to read wallpaper directory: {}", e))?;
-
-    let mut wallpapers = Vec::new();
-
-    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
-        let path = entry.path();
-        if path.is_file()
-            && matches!(
-                path.extension().and_then(|e| e.to_str()),
-                Some("png" | "jpg" | "jpeg")
-            )
-        {
-            let path_str = path
-                .to_string_lossy()
-                .replace(&std::env::var("HOME").unwrap_or_default(), "~");
-            wallpapers.push(path_str);
-        }
-    }
-
-    wallpapers
+async fn get_random_wallpaper() -> Result<String, HyprwallError> {
+    get_wallpapers()
+        .await?
         .choose(&mut rand::thread_rng())
-        .ok_or_else(|| "No wallpapers found".to_string())
+        .ok_or_else(|| "No wallpapers found".into())
         .map(|p| p.to_string())
 }
 
-pub fn set_wallpaper(path: String) {
-    let path = path.replace(&std::env::var("HOME").unwrap_or_default(), "~");
+async fn get_wallpapers() -> Result<Vec<String>, HyprwallError> {
+    let sources = Config::open(&config::path())?.source_paths();
+
+    if sources.is_empty() {
+        return Err("Wallpaper folder not found in config".into());
+    }
+
+    list_wallpapers(&sources).await
+}
+
+async fn list_library(sort: Option<sort::SortKey>) -> Result<(), HyprwallError> {
+    let sort = sort.unwrap_or_else(|| config::load().sort());
+    let mut paths: Vec<PathBuf> = get_wallpapers()
+        .await?
+        .iter()
+        .map(|path| PathBuf::from(shellexpand::tilde(path).into_owned()))
+        .collect();
+
+    let paths = tokio::task::spawn_blocking(move || {
+        sort::sort(&mut paths, sort);
+        paths
+    })
+    .await
+    .map_err(|e| format!("Failed to sort wallpapers: {}", e))?;
+
+    for path in paths {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+async fn list_wallpapers(folders: &[PathBuf]) -> Result<Vec<String>, HyprwallError> {
+    let (found, missing): (Vec<PathBuf>, Vec<PathBuf>) =
+        folders.iter().cloned().partition(|folder| folder.is_dir());
+
+    for folder in &missing {
+        eprintln!(
+            "Skipping wallpaper folder {}: not a directory",
+            folder.display()
+        );
+    }
+    if found.is_empty() {
+        return Err("Failed to read wallpaper directory: no folder is available".into());
+    }
+
+    let options = config::load().scan_options();
+    let paths =
+        tokio::task::spawn_blocking(move || scan::library(&found, wallpaper_formats(), &options))
+            .await
+            .map_err(|e| format!("Failed to read wallpaper directory: {}", e))?;
+
+    Ok(paths
+        .into_iter()
+        .map(|path| config::with_tilde(&path.to_string_lossy()))
+        .collect())
+}
+
+// Animated images are only picked when the backend can show them.
+pub fn wallpaper_formats() -> &'static [scan::Format] {
+    let gif = CURRENT_BACKEND
+        .lock()
+        .is_some_and(|backend| backend.capabilities().supports(scan::Format::Gif));
+    scan::formats(gif)
+}
+
+pub fn set_wallpaper(path: String, monitor: Option<String>) {
+    let path = config::with_tilde(&path);
     glib::spawn_future_local(async move {
-        match set_wallpaper_internal(&path).await {
+        match set_wallpaper_internal(&path, monitor.as_deref()).await {
             Ok(_) => {
                 println!("Wallpaper set successfully: {}", path);
-                gui::save_last_wallpaper(&path);
+                remember_wallpaper(&path, monitor.as_deref());
             }
             Err(e) => {
                 eprintln!("Error setting wallpaper: {}", e);
-                gui::custom_error_popup("Error setting wallpaper", &e, true);
+                gui::error_dialog("Error setting wallpaper", &e);
             }
         }
     });
 }
 
-async fn set_wallpaper_internal(path: &str) -> Result<(), String> {
+async fn set_wallpaper_internal(path: &str, monitor: Option<&str>) -> Result<(), HyprwallError> {
     let path = shellexpand::tilde(path).into_owned();
-    let current_backend = *CURRENT_BACKEND.lock();
-
-    kill_other_backends(current_backend).await;
-
-    ensure_backend_running().await?;
-
-    println!("Attempting to set wallpaper: {}", path);
-
-    let result = match current_backend {
-        WallpaperBackend::Hyprpaper => set_hyprpaper_wallpaper(&path).await,
-        WallpaperBackend::Swaybg => set_swaybg_wallpaper(&path).await,
-        WallpaperBackend::Swww => set_swww_wallpaper(&path).await,
-        WallpaperBackend::Wallutils => set_wallutils_wallpaper(&path).await,
-        WallpaperBackend::Feh => set_feh_wallpaper(&path).await,
-        WallpaperBackend::None => Err("No wallpaper backend set".to_string()),
+    let Some(current_backend) = *CURRENT_BACKEND.lock() else {
+        return Err("No wallpaper backend set".into());
     };
 
-    if result.is_ok() {
-        gui::save_wallpaper_backend(&current_backend);
-    }
-
-    result
-}
+    let wallpapers = match monitor {
+        Some(monitor) => monitor_assignments(current_backend, monitor, &path).await?,
+        None => vec![Assignment {
+            monitor: None,
+            path: path.clone(),
+        }],
+    };
 
-async fn kill_other_backends(current_backend: WallpaperBackend) {
-    let backends = [
-        ("hyprpaper", WallpaperBackend::Hyprpaper),
-        ("swaybg", WallpaperBackend::Swaybg),
-        ("swww-daemon", WallpaperBackend::Swww),
-    ];
+    println!("Attempting to set wallpaper: {}", path);
 
-    for (process_name, backend) in backends.iter() {
-        if *backend != current_backend {
-            let _ = TokioCommand::new("killall")
-                .arg(process_name)
-                .status()
-                .await;
-        }
-    }
+    apply_wallpapers(current_backend, &wallpapers).await
 }
 
-async fn set_hyprpaper_wallpaper(path: &str) -> Result<(), String> {
-    let preload_command = format!("hyprctl hyprpaper preload \"{}\"", path);
-    spawn_background_process(&preload_command).await?;
+async fn apply_wallpapers(
+    backend: &dyn Backend,
+    wallpapers: &[Assignment],
+) -> Result<(), HyprwallError> {
+    let wallpapers = convert::for_backend(backend, wallpapers).await?;
 
-    let monitors = get_monitors().await?;
+    kill_other_backends(backend).await;
 
-    if monitors.is_empty() {
-        return Err("No monitors detected".to_string());
-    }
+    backend.start().await?;
 
-    *MONITORS.lock() = monitors.clone();
+    let result = backend.set(&wallpapers).await;
 
-    for monitor in monitors {
-        let set_command = format!("hyprctl hyprpaper wallpaper \"{},{}\"", monitor, path);
-        spawn_background_process(&set_command).await?;
+    if result.is_ok() && !BACKEND_PICKED.load(Ordering::Relaxed) {
+        config::update(|config| config.set_backend(Some(backend)));
     }
 
-    Ok(())
+    result
 }
 
-async fn set_swaybg_wallpaper(path: &str) -> Result<(), String> {
-    let command = format!("swaybg -i \"{}\" -m fill &", path);
-    TokioCommand::new("sh")
-        .arg("-c")
-        .arg(&command)
-        .spawn()
-        .map_err(|e| format!("Failed to start swaybg: {}", e))?;
+async fn monitor_assignments(
+    backend: &dyn Backend,
+    monitor: &str,
+    path: &str,
+) -> Result<Vec<Assignment>, HyprwallError> {
+    if !backend.capabilities().per_monitor {
+        return Err(format!(
+            "{} does not support per-monitor wallpapers",
+            backend.display_name()
+        )
+        .into());
+    }
 
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-    if is_process_running("swaybg").await {
-        Ok(())
-    } else {
-        Err("swaybg failed to start or crashed immediately".to_string())
+    let monitors = monitor::get_monitors().await?;
+    if !monitors.iter().any(|name| name == monitor) {
+        return Err(format!(
+            "Monitor {} not found. Available monitors: {}",
+            monitor,
+            monitors.join(", ")
+        )
+        .into());
     }
-}
 
-async fn set_swww_wallpaper(path: &str) -> Result<(), String> {
-    let command = format!("swww img \"{}\"", path);
-    spawn_background_process(&command).await
+    // Backends like swaybg and feh redraw every output at once, so the other
+    // monitors keep whatever was saved for them.
+    let config = config::load();
+    let saved = config.monitor_wallpapers();
+    let fallback = fallback_wallpaper(&config);
+    // A positional backend can't skip a monitor, those without anything saved
+    // get the new wallpaper too.
+    let positional = backend.capabilities().positional;
+
+    Ok(monitors
+        .into_iter()
+        .filter_map(|name| {
+            let wallpaper = if name == monitor {
+                path.to_string()
+            } else {
+                match saved.get(&name).cloned().or_else(|| fallback.clone()) {
+                    Some(wallpaper) => wallpaper,
+                    None if positional => path.to_string(),
+                    None => return None,
+                }
+            };
+            Some(Assignment {
+                monitor: Some(name),
+                path: shellexpand::tilde(&wallpaper).into_owned(),
+            })
+        })
+        .collect())
 }
 
-async fn set_wallutils_wallpaper(path: &str) -> Result<(), String> {
-    let command = format!("setwallpaper \"{}\"", path);
-    spawn_background_process(&command).await
+fn fallback_wallpaper(config: &Config) -> Option<String> {
+    config
+        .default_wallpaper()
+        .or_else(|| config.last_wallpaper())
 }
 
-async fn set_feh_wallpaper(path: &str) -> Result<(), String> {
-    let command = format!("feh --bg-fill \"{}\"", path);
-    spawn_background_process(&command).await
+fn remember_wallpaper(path: &str, monitor: Option<&str>) {
+    sort::record_use(path);
+    config::update(|config| match monitor {
+        Some(monitor) => config.set_monitor_wallpaper(monitor, path),
+        None => {
+            config.set_last_wallpaper(path);
+            config.clear_monitor_wallpapers();
+        }
+    });
 }
 
-async fn spawn_background_process(command: &str) -> Result<(), String> {
-    let output = TokioCommand::new("sh")
-        .arg("-c")
-        .arg(command)
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute command '{}': {}", command, e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "Command '{}' failed with exit code {:?}.\nStdout: {}\nStderr: {}",
-            command,
-            output.status.code(),
-            stdout,
-            stderr
-        ));
+async fn kill_other_backends(current_backend: &dyn Backend) {
+    for other in backend::BACKENDS {
+        if !backend::same(*other, current_backend) {
+            other.stop().await;
+        }
     }
-
-    Ok(())
-}
-
-async fn get_monitors() -> Result<Vec<String>, String> {
-    println!("Retrieving monitor information");
-    let output = TokioCommand::new("hyprctl")
-        .arg("monitors")
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute hyprctl monitors: {}", e))?;
-
-    let monitors: Vec<String> = String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .filter_map(|line| {
-            if line.starts_with("Monitor ") {
-                let monitor_name = line.split_whitespace().nth(1).map(String::from);
-                println!("Found monitor: {:?}", monitor_name);
-                monitor_name
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    println!("Retrieved monitors: {:?}", monitors);
-    Ok(monitors)
 }
 
-async fn ensure_backend_running() -> Result<(), String> {
-    let backend = *CURRENT_BACKEND.lock();
-    match backend {
-        WallpaperBackend::Hyprpaper => ensure_hyprpaper_running().await,
-        WallpaperBackend::Swaybg => ensure_swaybg_running().await,
-        WallpaperBackend::Swww => ensure_swww_running().await,
-        WallpaperBackend::Wallutils => Ok(()),
-        WallpaperBackend::Feh => Ok(()),
-        WallpaperBackend::None => Err("No wallpaper backend set".to_string()),
+pub fn set_wallpaper_backend(backend: Option<&'static dyn Backend>) {
+    let previous_backend = {
+        let mut current = CURRENT_BACKEND.lock();
+        let prev = *current;
+        *current = backend;
+        BACKEND_PICKED.store(false, Ordering::Relaxed);
+        prev
+    };
+    if let Some(previous_backend) = previous_backend {
+        tokio::spawn(async move {
+            previous_backend.clear().await;
+            previous_backend.stop().await;
+        });
     }
+    config::update(|config| config.set_backend(backend));
 }
 
-async fn ensure_hyprpaper_running() -> Result<(), String> {
-    if !is_process_running("hyprpaper").await {
-        println!("hyprpaper is not running. Attempting to start it...");
-
-        let hyprpaper_config_path = tilde("~/.config/hypr/hyprpaper.conf").into_owned();
-        let hyprpaper_config_path = Path::new(&hyprpaper_config_path);
-
-        if !hyprpaper_config_path.exists() {
-            std::fs::create_dir_all(hyprpaper_config_path.parent().unwrap())
-                .expect("Failed to create ~/.config/hypr");
-            std::fs::File::create(hyprpaper_config_path)
-                .expect("Failed to create ~/.config/hypr/hyprpaper.conf");
+fn show_schedule(explain: bool, at: Option<chrono::NaiveTime>) {
+    let schedule = match schedule::Schedule::load() {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => {
+            println!("No [Schedule] section in the config");
+            return;
         }
+        Err(e) => fail("reading schedule", &e.into()),
+    };
 
-        start_process("hyprpaper").await?;
+    let time = at.unwrap_or_else(schedule::now);
+    if explain {
+        println!("{}", schedule::explain(&schedule, time));
+    } else {
+        match schedule.active_at(time) {
+            Some(entry) => println!("{}", entry.target),
+            None => println!("No scheduled wallpaper is active"),
+        }
     }
-    Ok(())
 }
 
-async fn ensure_swaybg_running() -> Result<(), String> {
-    if !is_process_running("swaybg").await {
-        println!("swaybg is not running. Attempting to start it...");
-        start_process("swaybg").await?;
+fn list_profiles() {
+    let config = config::load();
+    let active = config.active_profile().map(|profile| profile.name);
+    let profiles = config.profiles();
+
+    if profiles.is_empty() {
+        println!("No profiles in the config");
+    }
+    for name in profiles {
+        let marker = if active.as_ref() == Some(&name) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {}", marker, name);
     }
-    Ok(())
 }
 
-async fn ensure_swww_running() -> Result<(), String> {
-    if !is_process_running("swww-daemon").await {
-        println!("swww is not running. Attempting to start it...");
-        start_process("swww-daemon 2>/dev/null").await?;
+pub async fn use_profile(name: &str) -> Result<(), HyprwallError> {
+    let config = Config::open(&config::path())?;
+    let profile = config.profile(name).ok_or_else(|| {
+        format!(
+            "Profile {} not found. Available profiles: {}",
+            name,
+            config.profiles().join(", ")
+        )
+    })?;
+
+    config::update(|config| config.use_profile(&profile));
+    load_wallpaper_backend();
+    println!("Switched to profile {}", profile.name);
+
+    if !profile.monitors.is_empty() {
+        restore_wallpapers().await?;
+    } else if let Some(wallpaper) = &profile.wallpaper {
+        set_wallpaper_internal(wallpaper, None).await?;
+    } else if profile.folder.is_some() {
+        let path = get_random_wallpaper().await?;
+        set_wallpaper_internal(&path, None).await?;
+        remember_wallpaper(&path, None);
     }
+
+    // A running daemon picks up the new folder and rotation policy
+    let _ = ipc::send(&ipc::Request::Reload).await;
     Ok(())
 }
 
-async fn is_process_running(process_name: &str) -> bool {
-    TokioCommand::new("pgrep")
-        .arg("-x")
-        .arg(process_name)
-        .status()
-        .await
-        .map(|status| status.success())
-        .unwrap_or(false)
+fn restore_last_wallpaper() {
+    let rt = Runtime::new().expect("Failed to create Tokio runtime");
+    match rt.block_on(restore_wallpapers()) {
+        Ok(_) => println!("Wallpaper restored successfully"),
+        Err(e) => fail("restoring wallpaper", &e),
+    }
 }
 
-async fn start_process(command: &str) -> Result<(), String> {
-    TokioCommand::new("sh")
-        .arg("-c")
-        .arg(command)
-        .spawn()
-        .map_err(|e| format!("Failed to start {}: {}", command, e))?;
+async fn restore_wallpapers() -> Result<(), HyprwallError> {
+    let Some(current_backend) = *CURRENT_BACKEND.lock() else {
+        return Err("No wallpaper backend set".into());
+    };
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    if let Some(schedule) = schedule::Schedule::load()? {
+        if let Some(entry) = schedule.active_at(schedule::now()) {
+            println!("Restoring scheduled wallpaper: {}", entry);
+            let path = schedule::pick(&entry.target).await?;
+            return set_wallpaper_internal(&path, None).await;
+        }
+    }
 
-    if is_process_running(command.split_whitespace().next().unwrap_or(command)).await {
-        Ok(())
-    } else {
-        Err(format!("Failed to start {}", command))
+    let config = config::load();
+    let saved = config.monitor_wallpapers();
+    let fallback = fallback_wallpaper(&config);
+
+    if saved.is_empty() || !current_backend.capabilities().per_monitor {
+        let path = fallback.ok_or("No last wallpaper found to restore")?;
+        let wallpapers = [Assignment {
+            monitor: None,
+            path: shellexpand::tilde(&path).into_owned(),
+        }];
+        return apply_wallpapers(current_backend, &wallpapers).await;
     }
-}
 
-pub fn set_wallpaper_backend(backend: WallpaperBackend) {
-    let previous_backend = {
-        let mut current = CURRENT_BACKEND.lock();
-        let prev = *current;
-        *current = backend;
-        prev
-    };
-    tokio::spawn(async move {
-        drop_all_wallpapers(previous_backend).await;
-        kill_previous_backend(previous_backend).await;
-    });
-    gui::save_wallpaper_backend(&backend);
-}
+    let monitors = monitor::get_monitors().await?;
 
-async fn kill_previous_backend(backend: WallpaperBackend) {
-    let process_name = match backend {
-        WallpaperBackend::Hyprpaper => "hyprpaper",
-        WallpaperBackend::Swaybg => "swaybg",
-        WallpaperBackend::Swww => "swww-daemon",
-        WallpaperBackend::Wallutils => return,
-        WallpaperBackend::Feh => return,
-        WallpaperBackend::None => return,
-    };
+    for name in saved.keys() {
+        if !monitors.contains(name) {
+            println!("Monitor {} is not connected, skipping it", name);
+        }
+    }
 
-    let _ = TokioCommand::new("killall")
-        .arg(process_name)
-        .status()
-        .await;
-}
+    let monitor_count = monitors.len();
+    let wallpapers: Vec<Assignment> = monitors
+        .into_iter()
+        .filter_map(|name| {
+            let path = match saved.get(&name) {
+                Some(path) => path.clone(),
+                None => {
+                    println!("No saved wallpaper for monitor {}, using the default", name);
+                    fallback.clone()?
+                }
+            };
+            Some(Assignment {
+                monitor: Some(name),
+                path: shellexpand::tilde(&path).into_owned(),
+            })
+        })
+        .collect();
 
-async fn drop_all_wallpapers(backend: WallpaperBackend) {
-    match backend {
-        WallpaperBackend::Hyprpaper => {
-            let _ = TokioCommand::new("hyprctl")
-                .args(["hyprpaper", "unload", "all"])
-                .status()
-                .await;
-        }
-        WallpaperBackend::Swww => {
-            let _ = TokioCommand::new("swww").args(["clear"]).status().await;
-        }
-        _ => {}
+    // Leaving one out would move every later wallpaper onto the wrong screen
+    if current_backend.capabilities().positional && wallpapers.len() < monitor_count {
+        return Err(format!(
+            "{} needs a wallpaper for every monitor, set default_wallpaper to fill the rest",
+            current_backend.display_name()
+        )
+        .into());
     }
-}
 
-fn restore_last_wallpaper() {
-    if let Some(last_wallpaper) = gui::load_last_wallpaper() {
-        let rt = Runtime::new().expect("Failed to create Tokio runtime");
-        match rt.block_on(set_wallpaper_internal(&last_wallpaper)) {
-            Ok(_) => {
-                println!("Wallpaper restored successfully");
-                gui::save_last_wallpaper(&last_wallpaper);
-            }
-            Err(e) => {
-                eprintln!("Error restoring wallpaper: {}", e);
-            }
-        }
-    } else {
-        eprintln!("No last wallpaper found to restore");
+    if wallpapers.is_empty() {
+        return Err("No wallpaper found to restore for the connected monitors".into());
     }
+
+    apply_wallpapers(current_backend, &wallpapers).await
 }
 
+// With `backend = none` the best available backend for the session is used,
+// without writing it to the config, so it's picked again in another session.
 pub fn load_wallpaper_backend() {
-    if let Some(backend) = gui::load_wallpaper_backend() {
-        *CURRENT_BACKEND.lock() = backend;
+    if let Some(backend) = config::load().backend() {
+        *CURRENT_BACKEND.lock() = Some(backend);
+        BACKEND_PICKED.store(false, Ordering::Relaxed);
+        return;
+    }
+
+    let mut current = CURRENT_BACKEND.lock();
+    if current.is_none() {
+        let environment = backend::Environment::detect();
+        *current = backend::pick(&environment);
+        BACKEND_PICKED.store(current.is_some(), Ordering::Relaxed);
+        if let Some(backend) = *current {
+            println!(
+                "No backend configured, using {} for this {} session",
+                backend.display_name(),
+                environment
+            );
+        }
     }
 }
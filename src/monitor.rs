@@ -0,0 +1,73 @@
+use crate::backend::Cmd;
+use crate::error::HyprwallError;
+
+pub async fn get_monitors() -> Result<Vec<String>, HyprwallError> {
+    println!("Retrieving monitor information");
+
+    let mut monitors = Vec::new();
+    for (program, args, parse) in DETECTORS {
+        if let Some(found) = run_detector(program, args, *parse).await {
+            if !found.is_empty() {
+                monitors = found;
+                break;
+            }
+        }
+    }
+
+    if monitors.is_empty() {
+        return Err(HyprwallError::NoMonitors);
+    }
+
+    println!("Retrieved monitors: {:?}", monitors);
+    Ok(monitors)
+}
+
+// What every detector reports, None when it is missing or fails.
+pub async fn each_detector() -> Vec<(&'static str, Option<Vec<String>>)> {
+    let mut results = Vec::new();
+    for (program, args, parse) in DETECTORS {
+        results.push((*program, run_detector(program, args, *parse).await));
+    }
+    results
+}
+
+type Parser = fn(&str) -> Vec<String>;
+
+const DETECTORS: &[(&'static str, &[&str], Parser)] = &[
+    ("hyprctl", &["monitors"], parse_hyprctl),
+    ("wlr-randr", &[], parse_wlr_randr),
+    ("xrandr", &["--listmonitors"], parse_xrandr),
+];
+
+async fn run_detector(program: &'static str, args: &[&str], parse: Parser) -> Option<Vec<String>> {
+    let output = Cmd::new(program).args(args).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_hyprctl(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.starts_with("Monitor "))
+        .filter_map(|line| line.split_whitespace().nth(1).map(String::from))
+        .collect()
+}
+
+fn parse_wlr_randr(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with(char::is_whitespace))
+        .filter_map(|line| line.split_whitespace().next().map(String::from))
+        .collect()
+}
+
+fn parse_xrandr(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Monitors:"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().last().map(String::from))
+        .collect()
+}
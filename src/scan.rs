@@ -0,0 +1,635 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const IGNORE_FILE: &str = ".hyprwallignore";
+const DEFAULT_MAX_DEPTH: usize = 8;
+// SVG is text, so look a little further in for the root element
+const HEADER_SIZE: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    WebP,
+    Avif,
+    Bmp,
+    Tiff,
+    Jxl,
+    Svg,
+}
+
+pub const STILL: &[Format] = &[
+    Format::Png,
+    Format::Jpeg,
+    Format::WebP,
+    Format::Avif,
+    Format::Bmp,
+    Format::Tiff,
+    Format::Jxl,
+    Format::Svg,
+];
+pub const ALL: &[Format] = &[
+    Format::Png,
+    Format::Jpeg,
+    Format::Gif,
+    Format::WebP,
+    Format::Avif,
+    Format::Bmp,
+    Format::Tiff,
+    Format::Jxl,
+    Format::Svg,
+];
+
+impl Format {
+    fn from_header(header: &[u8]) -> Option<Self> {
+        match header {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(Format::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(Format::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Format::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Format::WebP),
+            [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => {
+                Some(Format::Avif)
+            }
+            [b'B', b'M', ..] if is_bmp(header) => Some(Format::Bmp),
+            [b'I', b'I', 0x2a, 0x00, ..] | [b'M', b'M', 0x00, 0x2a, ..] => Some(Format::Tiff),
+            // A bare codestream only starts with ff 0a, too little to tell it from
+            // other data, so only the container is recognised here. Either is
+            // found by its extension.
+            [0x00, 0x00, 0x00, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a, ..] => {
+                Some(Format::Jxl)
+            }
+            _ if is_svg(header) => Some(Format::Svg),
+            _ => None,
+        }
+    }
+}
+
+impl Format {
+    fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" | "jpe" => Some(Format::Jpeg),
+            "gif" => Some(Format::Gif),
+            "webp" => Some(Format::WebP),
+            "avif" => Some(Format::Avif),
+            "bmp" => Some(Format::Bmp),
+            "tif" | "tiff" => Some(Format::Tiff),
+            "jxl" => Some(Format::Jxl),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+}
+
+// "BM" alone starts plenty of text, so also check the size of the info header
+// that follows the file header, which has only ever had these values.
+fn is_bmp(header: &[u8]) -> bool {
+    header.get(14..18).is_some_and(|size| {
+        matches!(
+            u32::from_le_bytes([size[0], size[1], size[2], size[3]]),
+            12 | 40 | 52 | 56 | 108 | 124
+        )
+    })
+}
+
+fn is_svg(header: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<') && text.contains("<svg")
+}
+
+// Identifies an image by its first bytes, so extensionless and misnamed images
+// are found, and so convert.rs knows what a file really holds.
+pub fn sniff(path: &Path) -> Option<Format> {
+    read_header(path)
+        .ok()
+        .and_then(|header| Format::from_header(&header))
+}
+
+fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    fs::File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+// A known image extension is taken at its word, so big folders and network
+// mounts aren't opened file by file. Everything else is sniffed, which finds
+// extensionless and misnamed images, and a .gif that is really a PNG.
+fn is_wanted(path: &Path, formats: &[Format]) -> io::Result<bool> {
+    match Format::from_extension(path) {
+        Some(format) if formats.contains(&format) => Ok(true),
+        _ => Ok(Format::from_header(&read_header(path)?)
+            .is_some_and(|format| formats.contains(&format))),
+    }
+}
+
+pub fn formats(gif: bool) -> &'static [Format] {
+    if gif {
+        ALL
+    } else {
+        STILL
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    pub recursive: bool,
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            recursive: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+struct Rule {
+    pattern: Vec<char>,
+    negate: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+// The rules of one ignore file, matched against paths relative to its folder.
+struct Ignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    max_depth: usize,
+    formats: &'a [Format],
+    visited: HashSet<PathBuf>,
+    ignores: Vec<Ignore>,
+    found: Vec<PathBuf>,
+    unreadable: Vec<PathBuf>,
+}
+
+// What a walk found, and what it could not open on the way: folders it may
+// not list, broken symlinks and files that can't be read to tell what they are.
+pub struct Scan {
+    pub images: Vec<PathBuf>,
+    pub unreadable: Vec<PathBuf>,
+}
+
+// Walks `folder` for images in one of `formats`. Subfolders are only entered
+// when recursion is on, down to `max_depth` levels.
+pub fn images(folder: &Path, formats: &[Format], options: &Options) -> Vec<PathBuf> {
+    scan(folder, formats, options).images
+}
+
+pub fn scan(folder: &Path, formats: &[Format], options: &Options) -> Scan {
+    let mut walk = Walk {
+        root: folder,
+        max_depth: if options.recursive {
+            options.max_depth
+        } else {
+            0
+        },
+        formats,
+        visited: HashSet::new(),
+        ignores: Vec::new(),
+        found: Vec::new(),
+        unreadable: Vec::new(),
+    };
+    walk.dir(folder, 0);
+    Scan {
+        images: walk.found,
+        unreadable: walk.unreadable,
+    }
+}
+
+// Scans every source in order. A file reachable from more than one source,
+// through overlapping folders or symlinks, is only listed the first time.
+pub fn library(sources: &[PathBuf], formats: &[Format], options: &Options) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    sources
+        .iter()
+        .flat_map(|source| images(source, formats, options))
+        .filter(|path| seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+impl Walk<'_> {
+    fn dir(&mut self, dir: &Path, depth: usize) {
+        // Symlinked folders are followed, so guard against loops by the real path
+        let Ok(real) = fs::canonicalize(dir) else {
+            self.unreadable.push(dir.to_path_buf());
+            return;
+        };
+        if !self.visited.insert(real) {
+            return;
+        }
+
+        let Ok(entries) = fs::read_dir(dir) else {
+            self.unreadable.push(dir.to_path_buf());
+            return;
+        };
+
+        let pushed = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(contents) => {
+                self.ignores.push(Ignore {
+                    base: dir.strip_prefix(self.root).unwrap_or(dir).to_path_buf(),
+                    rules: parse_rules(&contents),
+                });
+                true
+            }
+            Err(_) => false,
+        };
+
+        let mut subdirs = Vec::new();
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_dir = path.is_dir();
+            let relative = path.strip_prefix(self.root).unwrap_or(&path);
+            if is_ignored(&self.ignores, relative, is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                if depth < self.max_depth {
+                    subdirs.push(path);
+                }
+            } else if path.is_file() {
+                match is_wanted(&path, self.formats) {
+                    Ok(true) => self.found.push(path),
+                    Ok(false) => {}
+                    Err(_) => self.unreadable.push(path),
+                }
+            } else if fs::metadata(&path).is_err() {
+                // A symlink to nothing, or to somewhere we may not look
+                self.unreadable.push(path);
+            }
+        }
+
+        for subdir in subdirs {
+            self.dir(&subdir, depth + 1);
+        }
+
+        if pushed {
+            self.ignores.pop();
+        }
+    }
+}
+
+fn parse_rules(contents: &str) -> Vec<Rule> {
+    contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (negate, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            // Like gitignore, a slash anywhere but the end ties the pattern to
+            // the folder holding the ignore file, otherwise it matches names
+            // at any depth.
+            let anchored = line.contains('/');
+            Rule {
+                pattern: line.trim_start_matches('/').chars().collect(),
+                negate,
+                dir_only,
+                anchored,
+            }
+        })
+        .collect()
+}
+
+// Later rules and deeper ignore files win, as in gitignore.
+fn is_ignored(ignores: &[Ignore], relative: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+
+    for ignore in ignores {
+        let Ok(path) = relative.strip_prefix(&ignore.base) else {
+            continue;
+        };
+        let path: Vec<char> = path.to_string_lossy().chars().collect();
+        let name = match path.iter().rposition(|c| *c == '/') {
+            Some(slash) => &path[slash + 1..],
+            None => &path[..],
+        };
+
+        for rule in &ignore.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let text = if rule.anchored { &path[..] } else { name };
+            if glob(&rule.pattern, text) {
+                ignored = !rule.negate;
+            }
+        }
+    }
+
+    ignored
+}
+
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=text.len())
+                .filter(|&i| i == 0 || text[i - 1] == '/')
+                .any(|i| glob(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob(rest, &text[i..])),
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => glob(rest, text),
+            _ => false,
+        },
+        ['[', rest @ ..] => match (class(rest), text) {
+            (Some((matches, rest)), [c, text @ ..]) if *c != '/' && matches(*c) => glob(rest, text),
+            (Some(_), _) => false,
+            (None, [c, text @ ..]) => *c == '[' && glob(rest, text),
+            (None, []) => false,
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => match text {
+            [t, text @ ..] if t == c => glob(rest, text),
+            _ => false,
+        },
+    }
+}
+
+// Parses the inside of `[...]`, returning a matcher and the pattern after `]`.
+fn class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, &[char])> {
+    let (negate, body) = match pattern {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    // A ']' right after the opening bracket is a literal
+    let end = body.iter().skip(1).position(|c| *c == ']')? + 1;
+    let (items, rest) = (&body[..end], &body[end + 1..]);
+
+    let matches = move |c: char| {
+        let mut i = 0;
+        let mut hit = false;
+        while i < items.len() {
+            if i + 2 < items.len() && items[i + 1] == '-' {
+                hit |= items[i] <= c && c <= items[i + 2];
+                i += 3;
+            } else {
+                hit |= items[i] == c;
+                i += 1;
+            }
+        }
+        hit != negate
+    };
+    Some((matches, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn ignored(rules: &str, path: &str, is_dir: bool) -> bool {
+        let ignores = [Ignore {
+            base: PathBuf::new(),
+            rules: parse_rules(rules),
+        }];
+        is_ignored(&ignores, Path::new(path), is_dir)
+    }
+
+    fn found(dir: &TempDir, options: &Options) -> Vec<String> {
+        let mut found: Vec<String> = images(dir.path(), ALL, options)
+            .iter()
+            .map(|path| {
+                path.strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn recursive(max_depth: usize) -> Options {
+        Options {
+            recursive: true,
+            max_depth,
+        }
+    }
+
+    #[test]
+    fn unanchored_patterns_match_names_at_any_depth() {
+        assert!(ignored("*.tmp.png", "x.tmp.png", false));
+        assert!(ignored("*.tmp.png", "a/b/x.tmp.png", false));
+        assert!(ignored("drafts", "a/drafts", true));
+        assert!(!ignored("*.tmp.png", "x.png", false));
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_the_ignore_file() {
+        assert!(ignored("/drafts", "drafts", true));
+        assert!(!ignored("/drafts", "a/drafts", true));
+        assert!(ignored("a/b.png", "a/b.png", false));
+        assert!(!ignored("a/b.png", "x/a/b.png", false));
+        // A single star stops at a slash
+        assert!(!ignored("a/*.png", "a/b/c.png", false));
+    }
+
+    #[test]
+    fn double_stars_span_folders() {
+        assert!(ignored("**/raw", "raw", true));
+        assert!(ignored("**/raw", "x/y/raw", true));
+        assert!(ignored("old/**", "old/a.png", false));
+        assert!(ignored("old/**", "old/a/b.png", false));
+        assert!(!ignored("old/**", "older/a.png", false));
+        assert!(ignored("a/**/b.png", "a/b.png", false));
+        assert!(ignored("a/**/b.png", "a/x/y/b.png", false));
+    }
+
+    #[test]
+    fn later_negations_win() {
+        assert!(!ignored("*.png\n!keep.png", "keep.png", false));
+        assert!(ignored("*.png\n!keep.png", "drop.png", false));
+        assert!(ignored("!keep.png\n*.png", "keep.png", false));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_folders() {
+        assert!(ignored("cache/", "cache", true));
+        assert!(!ignored("cache/", "cache", false));
+    }
+
+    #[test]
+    fn classes_and_wildcards() {
+        assert!(ignored("[a-c].png", "b.png", false));
+        assert!(!ignored("[a-c].png", "d.png", false));
+        assert!(ignored("[!x].png", "y.png", false));
+        assert!(!ignored("[!x].png", "x.png", false));
+        assert!(ignored("[^x].png", "y.png", false));
+        assert!(ignored("[]].png", "].png", false));
+        assert!(ignored("wall?.png", "wall1.png", false));
+        assert!(!ignored("wall?.png", "wall10.png", false));
+        // An unclosed bracket is a literal
+        assert!(ignored("[abc", "[abc", false));
+    }
+
+    #[test]
+    fn comments_and_escapes() {
+        assert!(!ignored("#hash.png", "#hash.png", false));
+        assert!(ignored("\\#hash.png", "#hash.png", false));
+        assert!(ignored("\\!bang.png", "!bang.png", false));
+        assert!(ignored("a\\*.png", "a*.png", false));
+        assert!(!ignored("a\\*.png", "ab.png", false));
+    }
+
+    #[test]
+    fn images_are_found_by_extension_or_content() {
+        let dir = TempDir::new("scan-formats");
+        dir.write("png-named.jpg", PNG);
+        dir.write("no-extension", PNG);
+        dir.write("UPPER.JPG", b"\xff\xd8\xff\xe0");
+        dir.write("drawing.svg", "<?xml version=\"1.0\"?>\n<svg></svg>");
+        dir.write("notes.txt", "not an image");
+        dir.write("README", "not an image either");
+        // Trusted by its extension, decoding reports it later
+        dir.write("fake.png", "just text");
+
+        assert_eq!(
+            found(&dir, &Options::default()),
+            [
+                "UPPER.JPG",
+                "drawing.svg",
+                "fake.png",
+                "no-extension",
+                "png-named.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn unwanted_extensions_are_checked_by_content() {
+        let dir = TempDir::new("scan-gif");
+        dir.write("still.gif", PNG);
+        dir.write("moving.gif", b"GIF89a\x01\0");
+
+        let still: Vec<PathBuf> = images(dir.path(), STILL, &Options::default());
+        assert_eq!(still, [dir.join("still.gif")]);
+        assert_eq!(images(dir.path(), ALL, &Options::default()).len(), 2);
+    }
+
+    #[test]
+    fn look_alike_text_is_not_an_image() {
+        let dir = TempDir::new("scan-look-alike");
+        assert_eq!(
+            sniff(&dir.write("BMW notes", "BMW service due in March")),
+            None
+        );
+        assert_eq!(sniff(&dir.write("bm.txt", "BM")), None);
+        assert_eq!(sniff(&dir.write("codestream", b"\xff\x0a\xfa\x7f")), None);
+        assert!(images(dir.path(), ALL, &Options::default()).is_empty());
+    }
+
+    #[test]
+    fn sniffing_reads_the_header() {
+        let dir = TempDir::new("scan-sniff");
+        assert_eq!(sniff(&dir.write("a.jpg", PNG)), Some(Format::Png));
+        assert_eq!(
+            sniff(&dir.write("b", b"RIFF\0\0\0\0WEBPVP8 ")),
+            Some(Format::WebP)
+        );
+        assert_eq!(
+            sniff(&dir.write("c", b"\0\0\0\x1cftypavif")),
+            Some(Format::Avif)
+        );
+        assert_eq!(
+            sniff(&dir.write("d", b"BM\x36\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0")),
+            Some(Format::Bmp)
+        );
+        assert_eq!(
+            sniff(&dir.write("e", b"\0\0\0\x0cJXL \r\n\x87\n")),
+            Some(Format::Jxl)
+        );
+        assert_eq!(sniff(&dir.write("d.png", "just text")), None);
+        assert_eq!(sniff(&dir.write("e.png", "")), None);
+        assert_eq!(sniff(&dir.join("missing.png")), None);
+    }
+
+    #[test]
+    fn nested_ignore_files_override_their_parent() {
+        let dir = TempDir::new("scan-nested");
+        dir.write(IGNORE_FILE, "*-draft.png\nskip/\n");
+        dir.write("a-draft.png", PNG);
+        dir.write("a.png", PNG);
+        dir.write("sub/.hyprwallignore", "!*-draft.png\n");
+        dir.write("sub/b-draft.png", PNG);
+        dir.write("skip/c.png", PNG);
+        // The nested file only applies below its own folder
+        dir.write("other/d-draft.png", PNG);
+
+        assert_eq!(
+            found(&dir, &recursive(DEFAULT_MAX_DEPTH)),
+            ["a.png", "sub/b-draft.png"]
+        );
+    }
+
+    #[test]
+    fn max_depth_limits_recursion() {
+        let dir = TempDir::new("scan-depth");
+        dir.write("0.png", PNG);
+        dir.write("a/1.png", PNG);
+        dir.write("a/b/2.png", PNG);
+        dir.write("a/b/c/3.png", PNG);
+
+        assert_eq!(found(&dir, &Options::default()), ["0.png"]);
+        assert_eq!(found(&dir, &recursive(1)), ["0.png", "a/1.png"]);
+        assert_eq!(
+            found(&dir, &recursive(DEFAULT_MAX_DEPTH)),
+            ["0.png", "a/1.png", "a/b/2.png", "a/b/c/3.png"]
+        );
+    }
+
+    #[test]
+    fn open_failures_are_reported() {
+        let dir = TempDir::new("scan-unreadable");
+        dir.write("0.png", PNG);
+        dir.write("notes", "not an image");
+        std::os::unix::fs::symlink(dir.join("gone.png"), dir.join("broken.png")).unwrap();
+        std::os::unix::fs::symlink(dir.join("gone"), dir.join("broken")).unwrap();
+
+        let scan = scan(dir.path(), ALL, &recursive(DEFAULT_MAX_DEPTH));
+        assert_eq!(scan.images, [dir.join("0.png")]);
+        let mut unreadable = scan.unreadable;
+        unreadable.sort();
+        assert_eq!(unreadable, [dir.join("broken"), dir.join("broken.png")]);
+    }
+
+    #[test]
+    fn symlink_loops_are_walked_once() {
+        let dir = TempDir::new("scan-loop");
+        dir.write("0.png", PNG);
+        dir.write("a/1.png", PNG);
+        std::os::unix::fs::symlink(dir.path(), dir.join("a/loop")).unwrap();
+
+        assert_eq!(
+            found(&dir, &recursive(DEFAULT_MAX_DEPTH)),
+            ["0.png", "a/1.png"]
+        );
+    }
+}
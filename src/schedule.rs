@@ -0,0 +1,460 @@
+use chrono::{Local, NaiveDate, NaiveTime, Timelike};
+use rand::seq::SliceRandom;
+
+use crate::config::{self, Config, ConfigError};
+use crate::error::HyprwallError;
+use crate::solar::Solar;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub target: String,
+}
+
+impl Entry {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{} = {}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            self.target
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    pub entries: Vec<Entry>,
+    pub solar: Option<Solar>,
+}
+
+impl Schedule {
+    pub fn load() -> Result<Option<Self>, ConfigError> {
+        let config = Config::open(&config::path())?;
+        Self::for_day(&config, Local::now().date_naive())
+    }
+
+    fn for_day(config: &Config, date: NaiveDate) -> Result<Option<Self>, ConfigError> {
+        let mut schedule = Self::from_config(config)?;
+
+        // Explicit time ranges come first so they win over the sun
+        if let Some(solar) = &schedule.solar {
+            let solar_entries = solar.entries(date);
+            schedule.entries.extend(solar_entries);
+        }
+
+        Ok((!schedule.entries.is_empty()).then_some(schedule))
+    }
+
+    fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut entries = Vec::new();
+
+        for (line, key, value) in config.section("Schedule") {
+            let error = |message: String| ConfigError::invalid(line, Some(key), message);
+            let (start, end) = key
+                .split_once(['-', '–'])
+                .ok_or_else(|| error("expected a time range like 06:00-18:00".to_string()))?;
+
+            entries.push(Entry {
+                start: parse_time(start).map_err(error)?,
+                end: parse_time(end).map_err(error)?,
+                target: value.to_string(),
+            });
+        }
+
+        let solar_section = config.section("Solar");
+        if solar_section.is_empty() {
+            return Ok(Self {
+                entries,
+                solar: None,
+            });
+        }
+
+        let section_line = solar_section[0].0;
+        let mut solar = Solar::default();
+        for (line, key, value) in solar_section {
+            let error = |message: String| ConfigError::invalid(line, Some(key), message);
+            match key {
+                "latitude" => solar.latitude = parse_coordinate(value, 90.0).map_err(error)?,
+                "longitude" => solar.longitude = parse_coordinate(value, 180.0).map_err(error)?,
+                "day" => solar.day = Some(value.to_string()),
+                "dusk" => solar.dusk = Some(value.to_string()),
+                "night" => solar.night = Some(value.to_string()),
+                _ => return Err(error("unknown [Solar] key".to_string())),
+            }
+        }
+
+        if solar.day.is_none() && solar.dusk.is_none() && solar.night.is_none() {
+            return Err(ConfigError::invalid(
+                section_line,
+                None,
+                "[Solar] needs at least one of day, dusk or night",
+            ));
+        }
+
+        Ok(Self {
+            entries,
+            solar: Some(solar),
+        })
+    }
+
+    pub fn active_at(&self, time: NaiveTime) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.contains(time))
+    }
+
+    pub fn until_next_change(&self, time: NaiveTime) -> Option<Duration> {
+        self.entries
+            .iter()
+            .flat_map(|entry| [entry.start, entry.end])
+            .map(|boundary| seconds_until(time, boundary))
+            .min()
+            .map(Duration::from_secs)
+    }
+}
+
+pub fn now() -> NaiveTime {
+    Local::now().time()
+}
+
+fn parse_coordinate(value: &str, limit: f64) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|coordinate| coordinate.abs() <= limit)
+        .ok_or_else(|| {
+            format!(
+                "invalid value '{}', expected a number between -{} and {}",
+                value, limit, limit
+            )
+        })
+}
+
+pub fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| format!("invalid time '{}', expected HH:MM", value.trim()))
+}
+
+fn seconds_until(from: NaiveTime, to: NaiveTime) -> u64 {
+    let from = from.num_seconds_from_midnight() as i64;
+    let to = to.num_seconds_from_midnight() as i64;
+    match (to - from).rem_euclid(DAY_SECONDS) {
+        0 => DAY_SECONDS as u64,
+        seconds => seconds as u64,
+    }
+}
+
+pub async fn wallpapers(target: &str) -> Result<Vec<String>, HyprwallError> {
+    let path = shellexpand::tilde(target).into_owned();
+    let path = Path::new(&path);
+
+    if path.is_dir() {
+        crate::list_wallpapers(&[path.to_path_buf()]).await
+    } else if path.is_file() {
+        Ok(vec![target.to_string()])
+    } else {
+        Err(format!("Scheduled wallpaper {} does not exist", target).into())
+    }
+}
+
+pub async fn pick(target: &str) -> Result<String, HyprwallError> {
+    wallpapers(target)
+        .await?
+        .choose(&mut rand::thread_rng())
+        .cloned()
+        .ok_or_else(|| format!("No wallpapers found in {}", target).into())
+}
+
+pub fn explain(schedule: &Schedule, time: NaiveTime) -> String {
+    let active = schedule.active_at(time);
+    let mut lines = vec![format!("Schedule at {}:", time.format("%H:%M"))];
+
+    if let Some(solar) = &schedule.solar {
+        lines.push(format!(
+            "  Sun times computed for {}, {}",
+            solar.latitude, solar.longitude
+        ));
+    }
+
+    for entry in &schedule.entries {
+        let marker = if Some(entry) == active {
+            "  (active)"
+        } else {
+            ""
+        };
+        lines.push(format!("  {}{}", entry, marker));
+    }
+
+    if active.is_none() {
+        lines.push("No entry is active, the regular wallpaper folder is used".to_string());
+    }
+
+    if let Some(until) = schedule.until_next_change(time) {
+        let minutes = until.as_secs() / 60;
+        lines.push(format!(
+            "Next change in {}h {:02}m",
+            minutes / 60,
+            minutes % 60
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        parse_time(value).unwrap()
+    }
+
+    fn entry(start: &str, end: &str, target: &str) -> Entry {
+        Entry {
+            start: time(start),
+            end: time(end),
+            target: target.to_string(),
+        }
+    }
+
+    fn ranges(entries: Vec<Entry>) -> Schedule {
+        Schedule {
+            entries,
+            solar: None,
+        }
+    }
+
+    fn invalid(contents: &str) -> (usize, Option<String>, String) {
+        let config = Config::parse(contents).unwrap();
+        match Schedule::from_config(&config) {
+            Err(ConfigError::Invalid { line, key, message }) => (line, key, message),
+            other => panic!("expected an invalid config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ranges_include_their_start_but_not_their_end() {
+        let day = entry("06:00", "18:00", "/day");
+        assert!(day.contains(time("06:00")));
+        assert!(day.contains(time("12:00")));
+        assert!(!day.contains(time("18:00")));
+        assert!(!day.contains(time("05:59")));
+    }
+
+    #[test]
+    fn ranges_wrap_past_midnight() {
+        let night = entry("22:00", "06:00", "/night");
+        assert!(night.contains(time("22:00")));
+        assert!(night.contains(time("23:59")));
+        assert!(night.contains(time("00:00")));
+        assert!(night.contains(time("05:59")));
+        assert!(!night.contains(time("06:00")));
+        assert!(!night.contains(time("12:00")));
+    }
+
+    #[test]
+    fn equal_start_and_end_cover_the_whole_day() {
+        let always = entry("09:00", "09:00", "/always");
+        assert!(always.contains(time("09:00")));
+        assert!(always.contains(time("08:59")));
+        assert!(always.contains(time("00:00")));
+    }
+
+    #[test]
+    fn schedule_lines_are_parsed() {
+        let config =
+            Config::parse("[Schedule]\n06:00-18:00 = /day\n22:00 – 06:00 = /night\n").unwrap();
+        let schedule = Schedule::from_config(&config).unwrap();
+        assert_eq!(
+            schedule,
+            Schedule {
+                entries: vec![
+                    entry("06:00", "18:00", "/day"),
+                    entry("22:00", "06:00", "/night")
+                ],
+                solar: None,
+            }
+        );
+    }
+
+    #[test]
+    fn bad_schedule_lines_name_their_line() {
+        assert_eq!(
+            invalid("[Schedule]\n0600 = /day\n"),
+            (
+                2,
+                Some("0600".to_string()),
+                "expected a time range like 06:00-18:00".to_string()
+            )
+        );
+        assert_eq!(
+            invalid("[Schedule]\n06:00-25:00 = /day\n"),
+            (
+                2,
+                Some("06:00-25:00".to_string()),
+                "invalid time '25:00', expected HH:MM".to_string()
+            )
+        );
+        assert_eq!(
+            invalid("[Schedule]\n6am-18:00 = /day\n").2,
+            "invalid time '6am', expected HH:MM"
+        );
+    }
+
+    #[test]
+    fn bad_solar_sections_name_their_line() {
+        assert_eq!(
+            invalid("[Solar]\nlatitude = 52.5\nsunrise = /day\n"),
+            (
+                3,
+                Some("sunrise".to_string()),
+                "unknown [Solar] key".to_string()
+            )
+        );
+        assert_eq!(
+            invalid("[Solar]\nlatitude = 91\nday = /day\n"),
+            (
+                2,
+                Some("latitude".to_string()),
+                "invalid value '91', expected a number between -90 and 90".to_string()
+            )
+        );
+        assert_eq!(
+            invalid("[Solar]\nlongitude = east\nday = /day\n").1,
+            Some("longitude".to_string())
+        );
+        assert_eq!(
+            invalid("[Solar]\nlatitude = 52.5\nlongitude = 13.4\n"),
+            (
+                2,
+                None,
+                "[Solar] needs at least one of day, dusk or night".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn next_change_is_the_nearest_boundary() {
+        let schedule = ranges(vec![
+            entry("06:00", "18:00", "/day"),
+            entry("18:00", "06:00", "/night"),
+        ]);
+        assert_eq!(
+            schedule.until_next_change(time("17:30")),
+            Some(Duration::from_secs(30 * 60))
+        );
+        // A boundary that is now has just passed, the next one is still ahead
+        assert_eq!(
+            schedule.until_next_change(time("06:00")),
+            Some(Duration::from_secs(12 * 60 * 60))
+        );
+        assert_eq!(
+            schedule.until_next_change(time("00:00")),
+            Some(Duration::from_secs(6 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn a_lone_boundary_comes_back_a_day_later() {
+        let schedule = ranges(vec![entry("09:00", "09:00", "/always")]);
+        assert_eq!(
+            schedule.until_next_change(time("09:00")),
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(ranges(Vec::new()).until_next_change(time("09:00")), None);
+    }
+
+    #[test]
+    fn explicit_ranges_win_over_the_sun() {
+        let config = Config::parse(concat!(
+            "[Schedule]\n",
+            "12:00-12:30 = /lunch\n",
+            "[Solar]\n",
+            "latitude = 52.52\n",
+            "longitude = 13.40\n",
+            "day = /day\n",
+            "dusk = /dusk\n",
+            "night = /night\n",
+        ))
+        .unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let schedule = Schedule::for_day(&config, date).unwrap().unwrap();
+
+        assert_eq!(schedule.entries[0], entry("12:00", "12:30", "/lunch"));
+        assert!(schedule.entries.len() > 1);
+
+        // The sun covers the whole day, the explicit range still wins
+        let solar = &schedule.entries[1..];
+        assert!(solar.iter().any(|entry| entry.contains(time("12:15"))));
+        assert_eq!(schedule.active_at(time("12:15")).unwrap().target, "/lunch");
+
+        for check in ["00:00", "06:00", "11:59", "12:30", "18:00", "23:59"] {
+            let active = schedule.active_at(time(check)).unwrap();
+            assert!(solar.contains(active), "{} at {}", active, check);
+        }
+    }
+
+    #[test]
+    fn without_entries_there_is_no_schedule() {
+        let config = Config::parse("[Settings]\nfolder = ~/walls\n").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        assert_eq!(Schedule::for_day(&config, date), Ok(None));
+    }
+
+    #[test]
+    fn explain_marks_the_active_entry() {
+        let schedule = ranges(vec![
+            entry("06:00", "18:00", "/day"),
+            entry("18:00", "06:00", "/night"),
+        ]);
+        assert_eq!(
+            explain(&schedule, time("07:30")),
+            concat!(
+                "Schedule at 07:30:\n",
+                "  06:00-18:00 = /day  (active)\n",
+                "  18:00-06:00 = /night\n",
+                "Next change in 10h 30m"
+            )
+        );
+    }
+
+    #[test]
+    fn explain_says_when_nothing_is_active() {
+        let schedule = Schedule {
+            entries: vec![entry("06:00", "07:00", "/dawn")],
+            solar: Some(Solar {
+                latitude: 52.52,
+                longitude: 13.4,
+                ..Solar::default()
+            }),
+        };
+        assert_eq!(
+            explain(&schedule, time("08:00")),
+            concat!(
+                "Schedule at 08:00:\n",
+                "  Sun times computed for 52.52, 13.4\n",
+                "  06:00-07:00 = /dawn\n",
+                "No entry is active, the regular wallpaper folder is used\n",
+                "Next change in 22h 00m"
+            )
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use crate::schedule::Entry;
+
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+const SECONDS_PER_DAY: f64 = 86400.0;
+const AXIAL_TILT: f64 = 23.4397;
+const SUNRISE_ALTITUDE: f64 = -0.833;
+const CIVIL_TWILIGHT_ALTITUDE: f64 = -6.0;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Solar {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub day: Option<String>,
+    pub dusk: Option<String>,
+    pub night: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Crossing<T> {
+    Between(T, T),
+    AlwaysAbove,
+    AlwaysBelow,
+}
+
+impl<T> Crossing<T> {
+    fn map<U>(self, f: impl Fn(T) -> U) -> Crossing<U> {
+        match self {
+            Crossing::Between(rise, set) => Crossing::Between(f(rise), f(set)),
+            Crossing::AlwaysAbove => Crossing::AlwaysAbove,
+            Crossing::AlwaysBelow => Crossing::AlwaysBelow,
+        }
+    }
+}
+
+// Sunrise equation, returns the unix timestamps at which the sun's centre
+// crosses the given altitude on the given day.
+fn crossing(days_since_epoch: i64, latitude: f64, longitude: f64, altitude: f64) -> Crossing<f64> {
+    let julian_day = days_since_epoch as f64 + UNIX_EPOCH_JULIAN_DAY;
+    let day_number = (julian_day - J2000 + 0.0008).ceil();
+    let mean_solar_time = day_number - longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+    let anomaly_rad = anomaly.to_radians();
+    let center = 1.9148 * anomaly_rad.sin()
+        + 0.0200 * (2.0 * anomaly_rad).sin()
+        + 0.0003 * (3.0 * anomaly_rad).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_solar_time + 0.0053 * anomaly_rad.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let sin_declination = ecliptic_longitude.sin() * AXIAL_TILT.to_radians().sin();
+    let cos_declination = (1.0 - sin_declination * sin_declination).sqrt();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (altitude.to_radians().sin() - latitude.sin() * sin_declination)
+        / (latitude.cos() * cos_declination);
+
+    if cos_hour_angle < -1.0 {
+        return Crossing::AlwaysAbove;
+    }
+    if cos_hour_angle > 1.0 {
+        return Crossing::AlwaysBelow;
+    }
+
+    let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+    Crossing::Between(transit - half_day, transit + half_day)
+        .map(|julian_day| (julian_day - UNIX_EPOCH_JULIAN_DAY) * SECONDS_PER_DAY)
+}
+
+fn local_time(timestamp: f64) -> NaiveTime {
+    Utc.timestamp_opt(timestamp.round() as i64, 0)
+        .single()
+        .expect("Sun event outside of the supported time range")
+        .with_timezone(&Local)
+        .time()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Day,
+    Dusk,
+    Night,
+}
+
+// Splits the day into phases by when the sun rises and sets and when civil
+// twilight begins and ends. In polar summer and winter some never happen.
+fn phases(
+    sun: Crossing<NaiveTime>,
+    civil: Crossing<NaiveTime>,
+) -> Vec<(NaiveTime, NaiveTime, Phase)> {
+    let midnight = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+    match (sun, civil) {
+        (Crossing::AlwaysAbove, _) => vec![(midnight, midnight, Phase::Day)],
+        (Crossing::AlwaysBelow, Crossing::Between(dawn, dusk_end)) => {
+            vec![
+                (dawn, dusk_end, Phase::Dusk),
+                (dusk_end, dawn, Phase::Night),
+            ]
+        }
+        (Crossing::AlwaysBelow, Crossing::AlwaysAbove) => vec![(midnight, midnight, Phase::Dusk)],
+        (Crossing::AlwaysBelow, Crossing::AlwaysBelow) => {
+            vec![(midnight, midnight, Phase::Night)]
+        }
+        (Crossing::Between(sunrise, sunset), Crossing::Between(dawn, dusk_end)) => vec![
+            (dawn, sunrise, Phase::Dusk),
+            (sunrise, sunset, Phase::Day),
+            (sunset, dusk_end, Phase::Dusk),
+            (dusk_end, dawn, Phase::Night),
+        ],
+        (Crossing::Between(sunrise, sunset), _) => vec![
+            (sunrise, sunset, Phase::Day),
+            (sunset, sunrise, Phase::Dusk),
+        ],
+    }
+}
+
+impl Solar {
+    pub fn entries(&self, date: NaiveDate) -> Vec<Entry> {
+        let days = (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+        let sun = crossing(days, self.latitude, self.longitude, SUNRISE_ALTITUDE).map(local_time);
+        let civil =
+            crossing(days, self.latitude, self.longitude, CIVIL_TWILIGHT_ALTITUDE).map(local_time);
+
+        let day = self
+            .day
+            .as_ref()
+            .or(self.dusk.as_ref())
+            .or(self.night.as_ref());
+        let dusk = self
+            .dusk
+            .as_ref()
+            .or(self.night.as_ref())
+            .or(self.day.as_ref());
+        let night = self
+            .night
+            .as_ref()
+            .or(self.dusk.as_ref())
+            .or(self.day.as_ref());
+
+        phases(sun, civil)
+            .into_iter()
+            .filter_map(|(start, end, phase)| {
+                let target = match phase {
+                    Phase::Day => day,
+                    Phase::Dusk => dusk,
+                    Phase::Night => night,
+                };
+                Some(Entry {
+                    start,
+                    end,
+                    target: target?.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERLIN: (f64, f64) = (52.52, 13.405);
+    const NEW_YORK: (f64, f64) = (40.7128, -74.006);
+    const TROMSO: (f64, f64) = (69.6492, 18.9553);
+
+    fn utc_crossing(
+        date: (i32, u32, u32),
+        place: (f64, f64),
+        altitude: f64,
+    ) -> Crossing<NaiveTime> {
+        let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+        let days = (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+        crossing(days, place.0, place.1, altitude).map(|timestamp| {
+            Utc.timestamp_opt(timestamp.round() as i64, 0)
+                .unwrap()
+                .time()
+        })
+    }
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    // Published times are rounded to the minute and assume a standard
+    // atmosphere, the equation is good to a few minutes at these latitudes.
+    fn assert_near(actual: NaiveTime, expected: &str) {
+        let difference = (actual - time(expected)).num_minutes().abs();
+        assert!(
+            difference.min(24 * 60 - difference) <= 4,
+            "{} is not near {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_between(crossing: Crossing<NaiveTime>, rise: &str, set: &str) {
+        let Crossing::Between(actual_rise, actual_set) = crossing else {
+            panic!("expected a sunrise and sunset, got {:?}", crossing);
+        };
+        assert_near(actual_rise, rise);
+        assert_near(actual_set, set);
+    }
+
+    #[test]
+    fn berlin_at_the_equinox() {
+        // 06:06 and 18:18 CET
+        let sun = utc_crossing((2024, 3, 20), BERLIN, SUNRISE_ALTITUDE);
+        assert_between(sun, "05:06", "17:18");
+    }
+
+    #[test]
+    fn new_york_at_the_solstice() {
+        // 05:25 and 20:31 EDT, the sunset falls on the next UTC day
+        let sun = utc_crossing((2024, 6, 20), NEW_YORK, SUNRISE_ALTITUDE);
+        assert_between(sun, "09:25", "00:31");
+    }
+
+    #[test]
+    fn civil_twilight_surrounds_the_day() {
+        let sun = utc_crossing((2024, 3, 20), BERLIN, SUNRISE_ALTITUDE);
+        let civil = utc_crossing((2024, 3, 20), BERLIN, CIVIL_TWILIGHT_ALTITUDE);
+        let (Crossing::Between(sunrise, sunset), Crossing::Between(dawn, dusk)) = (sun, civil)
+        else {
+            panic!("expected both crossings in Berlin");
+        };
+        assert!(dawn < sunrise && sunset < dusk);
+        // Around 35 minutes at this latitude in March
+        assert!((sunrise - dawn).num_minutes() > 25 && (sunrise - dawn).num_minutes() < 45);
+    }
+
+    #[test]
+    fn midnight_sun_in_tromso() {
+        let sun = utc_crossing((2024, 6, 21), TROMSO, SUNRISE_ALTITUDE);
+        assert_eq!(sun, Crossing::AlwaysAbove);
+    }
+
+    #[test]
+    fn polar_night_in_tromso_still_has_twilight() {
+        let sun = utc_crossing((2024, 12, 21), TROMSO, SUNRISE_ALTITUDE);
+        let civil = utc_crossing((2024, 12, 21), TROMSO, CIVIL_TWILIGHT_ALTITUDE);
+        assert_eq!(sun, Crossing::AlwaysBelow);
+        let Crossing::Between(dawn, dusk) = civil else {
+            panic!("expected civil twilight, got {:?}", civil);
+        };
+        // Solar noon is around 10:43 UTC
+        assert!(dawn < time("10:43") && time("10:43") < dusk);
+    }
+
+    #[test]
+    fn each_combination_of_crossings_has_phases() {
+        let (a, b, c, d) = (time("05:00"), time("06:00"), time("18:00"), time("19:00"));
+        let midnight = time("00:00");
+        let cases = [
+            (
+                Crossing::AlwaysAbove,
+                Crossing::AlwaysAbove,
+                vec![(midnight, midnight, Phase::Day)],
+            ),
+            (
+                Crossing::AlwaysBelow,
+                Crossing::Between(b, c),
+                vec![(b, c, Phase::Dusk), (c, b, Phase::Night)],
+            ),
+            (
+                Crossing::AlwaysBelow,
+                Crossing::AlwaysAbove,
+                vec![(midnight, midnight, Phase::Dusk)],
+            ),
+            (
+                Crossing::AlwaysBelow,
+                Crossing::AlwaysBelow,
+                vec![(midnight, midnight, Phase::Night)],
+            ),
+            (
+                Crossing::Between(b, c),
+                Crossing::Between(a, d),
+                vec![
+                    (a, b, Phase::Dusk),
+                    (b, c, Phase::Day),
+                    (c, d, Phase::Dusk),
+                    (d, a, Phase::Night),
+                ],
+            ),
+            (
+                Crossing::Between(b, c),
+                Crossing::AlwaysAbove,
+                vec![(b, c, Phase::Day), (c, b, Phase::Dusk)],
+            ),
+        ];
+        for (sun, civil, expected) in cases {
+            assert_eq!(phases(sun, civil), expected, "{:?} {:?}", sun, civil);
+        }
+    }
+
+    #[test]
+    fn missing_targets_fall_back() {
+        let solar = Solar {
+            latitude: TROMSO.0,
+            longitude: TROMSO.1,
+            night: Some("~/night".to_string()),
+            ..Solar::default()
+        };
+        let entries = solar.entries(NaiveDate::from_ymd_opt(2024, 6, 21).unwrap());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].target, "~/night");
+    }
+}
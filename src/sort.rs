@@ -0,0 +1,328 @@
+use crate::config;
+use gtk::gdk_pixbuf::Pixbuf;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LAST_USED_FILE: &str = "last_used";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Modified,
+    Size,
+    Resolution,
+    LastUsed,
+}
+
+pub const KEYS: &[SortKey] = &[
+    SortKey::Name,
+    SortKey::Modified,
+    SortKey::Size,
+    SortKey::Resolution,
+    SortKey::LastUsed,
+];
+
+impl SortKey {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        KEYS.iter()
+            .copied()
+            .find(|key| key.name() == value)
+            .ok_or_else(|| {
+                let names: Vec<&str> = KEYS.iter().map(|key| key.name()).collect();
+                format!(
+                    "unknown sort '{}', expected one of {}",
+                    value,
+                    names.join(", ")
+                )
+            })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Modified => "modified",
+            SortKey::Size => "size",
+            SortKey::Resolution => "resolution",
+            SortKey::LastUsed => "last_used",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "Name",
+            SortKey::Modified => "Date modified",
+            SortKey::Size => "File size",
+            SortKey::Resolution => "Resolution",
+            SortKey::LastUsed => "Last used",
+        }
+    }
+}
+
+// What a wallpaper is ordered by under a key, read once per file since stat
+// and header reads are too slow to repeat for every comparison. For names
+// every rank is None and the name decides.
+pub struct Ranker {
+    key: SortKey,
+    used: HashMap<PathBuf, u64>,
+}
+
+impl Ranker {
+    pub fn new(key: SortKey) -> Self {
+        let used = match key {
+            SortKey::LastUsed => last_used(&last_used_path()),
+            _ => HashMap::new(),
+        };
+        Self { key, used }
+    }
+
+    pub fn rank(&self, path: &Path) -> Option<u64> {
+        match self.key {
+            SortKey::Name => None,
+            SortKey::Modified => fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+            SortKey::Size => fs::metadata(path).map(|m| m.len()).ok(),
+            SortKey::Resolution => {
+                Pixbuf::file_info(path).map(|(_, width, height)| width as u64 * height as u64)
+            }
+            SortKey::LastUsed => self.used.get(path).copied(),
+        }
+    }
+}
+
+// Names sort naturally, every other key puts the newest, biggest or most
+// recently used first. Ties fall back to the name, so the order never depends
+// on how the folders were scanned.
+pub fn compare(a: &Path, a_rank: Option<u64>, b: &Path, b_rank: Option<u64>) -> Ordering {
+    // Reversed, so images without a rank end up last
+    b_rank.cmp(&a_rank).then_with(|| natural_cmp(a, b))
+}
+
+pub fn sort(paths: &mut [PathBuf], key: SortKey) {
+    let ranker = Ranker::new(key);
+    let mut ranked: Vec<(Option<u64>, PathBuf)> = paths
+        .iter()
+        .map(|path| (ranker.rank(path), path.clone()))
+        .collect();
+    ranked.sort_by(|(a_rank, a), (b_rank, b)| compare(a, *a_rank, b, *b_rank));
+    for (slot, (_, path)) in paths.iter_mut().zip(ranked) {
+        *slot = path;
+    }
+}
+
+// Compares file names ignoring case, with runs of digits compared by value so
+// that wall2 comes before wall10.
+pub fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+    natural(&name(a), &name(b)).then_with(|| a.cmp(b))
+}
+
+fn natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (number(&mut a), number(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let order = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn number(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+fn last_used_path() -> PathBuf {
+    config::cache_dir().join(LAST_USED_FILE)
+}
+
+// One `<unix time>\t<path>` line per wallpaper that has been set.
+fn last_used(file: &Path) -> HashMap<PathBuf, u64> {
+    let contents = fs::read_to_string(file).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (time, path) = line.split_once('\t')?;
+            Some((PathBuf::from(path), time.parse().ok()?))
+        })
+        .collect()
+}
+
+pub fn record_use(path: &str) {
+    if let Err(e) = record_use_in(&last_used_path(), path) {
+        eprintln!("Failed to record wallpaper use: {}", e);
+    }
+}
+
+// Locked like the config, so the daemon and the GUI recording at once don't
+// drop each other's entries.
+fn record_use_in(file: &Path, path: &str) -> io::Result<()> {
+    let path = PathBuf::from(shellexpand::tilde(path).into_owned());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    let _lock = config::lock(file)?;
+    let mut used = last_used(file);
+    used.insert(path, now);
+
+    let contents: String = used
+        .iter()
+        .map(|(path, time)| format!("{}\t{}\n", time, path.display()))
+        .collect();
+    config::write_atomic(file, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut paths: Vec<&Path> = names.iter().map(Path::new).collect();
+        paths.sort_by(|a, b| natural_cmp(a, b));
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(
+            sorted(&["img10.png", "img2.png", "img1.png"]),
+            ["img1.png", "img2.png", "img10.png"]
+        );
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(
+            sorted(&["img8.png", "img007.png", "img7.png"]),
+            ["img7.png", "img007.png", "img8.png"]
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_until_it_is_the_only_difference() {
+        assert_eq!(
+            sorted(&["Beach.png", "alps.png", "coast.png"]),
+            ["alps.png", "Beach.png", "coast.png"]
+        );
+        assert_eq!(sorted(&["wall.png", "WALL.png"]), ["WALL.png", "wall.png"]);
+    }
+
+    #[test]
+    fn text_and_digits_mix() {
+        assert_eq!(
+            sorted(&["a1b10", "a1b2", "a10", "a9z", "a", "a1"]),
+            ["a", "a1", "a1b2", "a1b10", "a9z", "a10"]
+        );
+    }
+
+    #[test]
+    fn only_the_file_name_counts_before_the_path() {
+        assert_eq!(
+            sorted(&["/z/img1.png", "/a/img2.png", "/b/img1.png"]),
+            ["/b/img1.png", "/z/img1.png", "/a/img2.png"]
+        );
+    }
+
+    #[test]
+    fn higher_ranks_come_first_and_unranked_last() {
+        let mut ranked = [
+            (Path::new("/w/unranked.png"), None),
+            (Path::new("/w/old.png"), Some(10)),
+            (Path::new("/w/new.png"), Some(20)),
+            (Path::new("/w/b.png"), Some(15)),
+            (Path::new("/w/a.png"), Some(15)),
+            (Path::new("/v/a.png"), Some(15)),
+        ];
+        ranked.sort_by(|(a, a_rank), (b, b_rank)| compare(a, *a_rank, b, *b_rank));
+        let order: Vec<&Path> = ranked.iter().map(|(path, _)| *path).collect();
+        assert_eq!(
+            order,
+            [
+                "/w/new.png",
+                "/v/a.png",
+                "/w/a.png",
+                "/w/b.png",
+                "/w/old.png",
+                "/w/unranked.png"
+            ]
+            .map(Path::new)
+        );
+    }
+
+    #[test]
+    fn sort_keys_parse_by_name() {
+        for key in KEYS {
+            assert_eq!(SortKey::parse(key.name()), Ok(*key));
+        }
+        assert_eq!(
+            SortKey::parse("random"),
+            Err(
+                "unknown sort 'random', expected one of name, modified, size, resolution, last_used"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn concurrent_uses_are_all_recorded() {
+        let dir = TempDir::new("sort-last-used");
+        let file = dir.join("last_used");
+
+        std::thread::scope(|scope| {
+            for thread in 0..16 {
+                let file = &file;
+                scope.spawn(move || {
+                    for round in 0..10 {
+                        let path = format!("/walls/{}-{}.png", thread, round);
+                        record_use_in(file, &path).unwrap();
+                    }
+                });
+            }
+        });
+
+        let used = last_used(&file);
+        assert_eq!(used.len(), 16 * 10);
+        assert!(used.contains_key(Path::new("/walls/15-9.png")));
+    }
+}
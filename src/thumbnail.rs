@@ -0,0 +1,325 @@
+use crate::config;
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::{gio, glib, prelude::*};
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MTIME: &str = "tEXt::Thumb::MTime";
+const URI: &str = "tEXt::Thumb::URI";
+const SOFTWARE: &str = "tEXt::Software";
+const FAIL_DIR: &str = concat!("fail/hyprwall-", env!("CARGO_PKG_VERSION"));
+const CONVERTED_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// Sizes from the freedesktop thumbnail spec, shared with file managers and
+// image viewers.
+const SIZES: &[(&str, i32)] = &[("normal", 128), ("large", 256)];
+
+#[derive(Debug, Default)]
+pub struct Pruned {
+    pub thumbnails: usize,
+    pub converted: usize,
+}
+
+pub fn root() -> PathBuf {
+    config::cache_home().join("thumbnails")
+}
+
+// Returns the cached thumbnail if it's still valid for the image, otherwise
+// renders and stores a new one.
+pub fn load(path: &Path, size: i32) -> Option<Pixbuf> {
+    load_in(&root(), path, size)
+}
+
+fn load_in(root: &Path, path: &Path, size: i32) -> Option<Pixbuf> {
+    let uri = gio::File::for_path(path).uri().to_string();
+    let mtime = mtime(path)?;
+    let name = name(&uri)?;
+    let (dir, pixels) = size_for(size);
+
+    let thumbnail = root.join(dir).join(&name);
+    if let Some(pixbuf) = cached(&thumbnail, mtime) {
+        return Some(pixbuf);
+    }
+
+    let failed = root.join(FAIL_DIR).join(&name);
+    if cached(&failed, mtime).is_some() {
+        return None;
+    }
+
+    match Pixbuf::from_file_at_scale(path, pixels, pixels, true) {
+        Ok(pixbuf) => {
+            if let Err(e) = store(&pixbuf, &thumbnail, &uri, mtime) {
+                eprintln!("Failed to save thumbnail for {}: {}", path.display(), e);
+            }
+            Some(pixbuf)
+        }
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", path.display(), e);
+            // A placeholder keeps us from decoding a broken file on every launch
+            if let Some(placeholder) = Pixbuf::new(Colorspace::Rgb, true, 8, 1, 1) {
+                let _ = store(&placeholder, &failed, &uri, mtime);
+            }
+            None
+        }
+    }
+}
+
+// The smallest size that still covers the request, big requests get the largest.
+fn size_for(size: i32) -> (&'static str, i32) {
+    SIZES
+        .iter()
+        .find(|(_, pixels)| size <= *pixels)
+        .copied()
+        .unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+fn name(uri: &str) -> Option<String> {
+    let checksum = glib::compute_checksum_for_string(glib::ChecksumType::Md5, uri)?;
+    Some(format!("{}.png", checksum))
+}
+
+fn mtime(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+fn cached(thumbnail: &Path, mtime: u64) -> Option<Pixbuf> {
+    let pixbuf = Pixbuf::from_file(thumbnail).ok()?;
+    let stored = pixbuf.option(MTIME)?;
+    (stored.parse::<u64>().ok()? == mtime).then_some(pixbuf)
+}
+
+// The spec asks for a private file renamed into place, so other programs
+// never read a partial thumbnail.
+fn store(pixbuf: &Pixbuf, thumbnail: &Path, uri: &str, mtime: u64) -> Result<(), String> {
+    let dir = thumbnail.parent().ok_or("Invalid thumbnail path")?;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let temp = thumbnail.with_extension(format!("{}.tmp", std::process::id()));
+    let mtime = mtime.to_string();
+    let software = format!("hyprwall {}", env!("CARGO_PKG_VERSION"));
+    pixbuf
+        .savev(
+            &temp,
+            "png",
+            &[(URI, uri), (MTIME, &mtime), (SOFTWARE, &software)],
+        )
+        .map_err(|e| e.to_string())?;
+
+    let _ = fs::set_permissions(&temp, fs::Permissions::from_mode(0o600));
+    fs::rename(&temp, thumbnail).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.to_string()
+    })
+}
+
+// Drops thumbnails whose image is gone or has changed since, in every size
+// and including other programs' entries, plus converted wallpapers that
+// haven't been used in a while.
+pub fn prune() -> Pruned {
+    prune_in(&root(), &config::cache_dir().join("converted"))
+}
+
+fn prune_in(root: &Path, converted: &Path) -> Pruned {
+    let mut pruned = Pruned::default();
+    let dirs = SIZES
+        .iter()
+        .map(|(dir, _)| root.join(dir))
+        .chain([root.join(FAIL_DIR)]);
+
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let thumbnail = entry.path();
+            // Other programs write their temp files here too
+            if thumbnail
+                .extension()
+                .is_none_or(|extension| extension != "png")
+            {
+                continue;
+            }
+            if is_stale(&thumbnail) && fs::remove_file(&thumbnail).is_ok() {
+                pruned.thumbnails += 1;
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(converted) {
+        let now = SystemTime::now();
+        for entry in entries.flatten() {
+            let old = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified).unwrap_or_default() > CONVERTED_MAX_AGE
+                });
+            if old && fs::remove_file(entry.path()).is_ok() {
+                pruned.converted += 1;
+            }
+        }
+    }
+
+    pruned
+}
+
+// Only thumbnails that name a local image and its modification time can be
+// judged, anything without both is left for its owner.
+fn is_stale(thumbnail: &Path) -> bool {
+    let Ok(pixbuf) = Pixbuf::from_file(thumbnail) else {
+        return false;
+    };
+    let (Some(uri), Some(stored)) = (pixbuf.option(URI), pixbuf.option(MTIME)) else {
+        return false;
+    };
+    let Ok(stored) = stored.parse::<u64>() else {
+        return false;
+    };
+
+    let file = gio::File::for_uri(&uri);
+    if !file.has_uri_scheme("file") {
+        return false;
+    }
+    let Some(path) = file.path() else {
+        return false;
+    };
+    mtime(&path) != Some(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn image(dir: &TempDir, name: &str, width: i32, height: i32) -> PathBuf {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, width, height).unwrap();
+        pixbuf.fill(0x3366_99ff);
+        let path = dir.join(name);
+        pixbuf.savev(&path, "png", &[]).unwrap();
+        path
+    }
+
+    fn uri(path: &Path) -> String {
+        gio::File::for_path(path).uri().to_string()
+    }
+
+    fn thumbnail_for(dir: &TempDir, source: &Path, mtime: Option<u64>) -> PathBuf {
+        let uri = uri(source);
+        let thumbnail = dir.join("thumbnails/normal").join(name(&uri).unwrap());
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, 4, 4).unwrap();
+        match mtime {
+            Some(mtime) => store(&pixbuf, &thumbnail, &uri, mtime).unwrap(),
+            None => {
+                fs::create_dir_all(thumbnail.parent().unwrap()).unwrap();
+                pixbuf
+                    .savev(&thumbnail, "png", &[(URI, uri.as_str())])
+                    .unwrap();
+            }
+        }
+        thumbnail
+    }
+
+    #[test]
+    fn thumbnails_are_named_after_the_uri_md5() {
+        // The example from the freedesktop thumbnail spec
+        assert_eq!(
+            name("file:///home/jens/photos/me.png").as_deref(),
+            Some("c6ee772d9e49320e97ec29a7eb5b1697.png")
+        );
+    }
+
+    #[test]
+    fn requests_pick_the_smallest_size_that_covers_them() {
+        assert_eq!(size_for(64), ("normal", 128));
+        assert_eq!(size_for(128), ("normal", 128));
+        assert_eq!(size_for(129), ("large", 256));
+        assert_eq!(size_for(1024), ("large", 256));
+    }
+
+    #[test]
+    fn loading_stores_the_thumbnail_in_its_size() {
+        let dir = TempDir::new("thumbnail-load");
+        let root = dir.join("thumbnails");
+        let source = image(&dir, "wide.png", 400, 200);
+        let name = name(&uri(&source)).unwrap();
+
+        let normal = load_in(&root, &source, 100).unwrap();
+        assert_eq!((normal.width(), normal.height()), (128, 64));
+        let large = load_in(&root, &source, 200).unwrap();
+        assert_eq!((large.width(), large.height()), (256, 128));
+
+        let stored = Pixbuf::from_file(root.join("normal").join(&name)).unwrap();
+        assert_eq!(stored.option(URI).as_deref(), Some(uri(&source).as_str()));
+        assert_eq!(
+            stored.option(MTIME).map(String::from),
+            mtime(&source).map(|mtime| mtime.to_string())
+        );
+        assert!(root.join("large").join(&name).is_file());
+    }
+
+    #[test]
+    fn broken_images_are_remembered_as_failed() {
+        let dir = TempDir::new("thumbnail-fail");
+        let root = dir.join("thumbnails");
+        let source = dir.write("broken.png", "not a png");
+
+        assert!(load_in(&root, &source, 128).is_none());
+        let failed = root.join(FAIL_DIR).join(name(&uri(&source)).unwrap());
+        assert!(failed.is_file());
+        assert!(!root.join("normal").exists());
+    }
+
+    #[test]
+    fn thumbnails_go_stale_when_their_image_changes_or_goes() {
+        let dir = TempDir::new("thumbnail-stale");
+        let source = image(&dir, "a.png", 8, 8);
+        let current = mtime(&source).unwrap();
+
+        assert!(!is_stale(&thumbnail_for(&dir, &source, Some(current))));
+        assert!(is_stale(&thumbnail_for(&dir, &source, Some(current - 60))));
+
+        fs::remove_file(&source).unwrap();
+        assert!(is_stale(&thumbnail_for(&dir, &source, Some(current))));
+    }
+
+    #[test]
+    fn thumbnails_without_an_mtime_are_left_alone() {
+        let dir = TempDir::new("thumbnail-no-mtime");
+        let source = dir.join("gone.png");
+        assert!(!is_stale(&thumbnail_for(&dir, &source, None)));
+
+        let remote = dir.join("thumbnails/normal/remote.png");
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, 4, 4).unwrap();
+        store(&pixbuf, &remote, "sftp://host/a.png", 1).unwrap();
+        assert!(!is_stale(&remote));
+    }
+
+    #[test]
+    fn pruning_only_removes_thumbnails_of_missing_images() {
+        let dir = TempDir::new("thumbnail-prune");
+        let kept = image(&dir, "kept.png", 8, 8);
+        let gone = image(&dir, "gone.png", 8, 8);
+        let kept_thumbnail = thumbnail_for(&dir, &kept, mtime(&kept));
+        let gone_thumbnail = thumbnail_for(&dir, &gone, mtime(&gone));
+        fs::remove_file(&gone).unwrap();
+        let other = dir.write("thumbnails/normal/viewer.tmp", "partial");
+
+        let pruned = prune_in(&dir.join("thumbnails"), &dir.join("converted"));
+        assert_eq!(pruned.thumbnails, 1);
+        assert!(kept_thumbnail.exists());
+        assert!(!gone_thumbnail.exists());
+        assert!(other.exists());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::scan;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// Quiet time before a burst of events is handled, and the longest a steady
+// stream of events (a big copy) can hold it back.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+// Stops watching when dropped.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+}
+
+// Watches the sources and reports which images came, went or were rewritten.
+// Sources that can't be watched are skipped, it only fails when none can.
+// Each burst of events is checked against a fresh scan, so depth limits,
+// .hyprwallignore and de-duplication apply exactly as in a full load.
+pub fn watch(
+    sources: Vec<PathBuf>,
+    formats: &'static [scan::Format],
+    options: scan::Options,
+    mut on_change: impl FnMut(Changes) + Send + 'static,
+) -> Result<Watcher, String> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })
+    .map_err(|e| format!("Failed to start folder watcher: {}", e))?;
+
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    // A folder on an unmounted drive shouldn't stop updates for the others
+    let mut watched = 0;
+    for source in &sources {
+        match watcher.watch(source, mode) {
+            Ok(()) => watched += 1,
+            Err(e) => eprintln!("Not watching {}: {}", source.display(), e),
+        }
+    }
+    if watched == 0 {
+        return Err("Failed to watch any wallpaper folder".to_string());
+    }
+
+    std::thread::spawn(move || {
+        let mut known: HashSet<PathBuf> = scan::library(&sources, formats, &options)
+            .into_iter()
+            .collect();
+
+        // The channel closes once the watcher is dropped
+        while let Ok(first) = receiver.recv() {
+            let mut touched = HashSet::new();
+            collect(&mut touched, first);
+
+            let started = Instant::now();
+            loop {
+                let wait = DEBOUNCE.min(MAX_DELAY.saturating_sub(started.elapsed()));
+                if wait.is_zero() {
+                    break;
+                }
+                match receiver.recv_timeout(wait) {
+                    Ok(event) => collect(&mut touched, event),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            if touched.is_empty() {
+                continue;
+            }
+
+            let current: HashSet<PathBuf> = scan::library(&sources, formats, &options)
+                .into_iter()
+                .collect();
+            let changes = Changes {
+                added: current.difference(&known).cloned().collect(),
+                removed: known.difference(&current).cloned().collect(),
+                modified: touched
+                    .into_iter()
+                    .filter(|path| known.contains(path) && current.contains(path))
+                    .collect(),
+            };
+            known = current;
+
+            if !changes.is_empty() {
+                on_change(changes);
+            }
+        }
+    });
+
+    Ok(Watcher { _watcher: watcher })
+}
+
+fn collect(touched: &mut HashSet<PathBuf>, event: notify::Result<Event>) {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => touched.extend(event.paths),
+        Ok(_) => {}
+        Err(e) => eprintln!("Folder watcher error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn missing_sources_are_skipped() {
+        let dir = TempDir::new("watch-missing");
+        let present = dir.join("present");
+        std::fs::create_dir(&present).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let watcher = watch(
+            vec![dir.join("unmounted"), present.clone()],
+            scan::ALL,
+            scan::Options::default(),
+            move |changes| {
+                let _ = sender.send(changes);
+            },
+        )
+        .unwrap();
+
+        let image = dir.write("present/a.png", PNG);
+        let changes = receiver.recv_timeout(MAX_DELAY * 2).unwrap();
+        // Added, or modified if the first scan already saw it
+        let mut reported = changes.added;
+        reported.extend(changes.modified);
+        assert_eq!(reported, [image]);
+        drop(watcher);
+    }
+
+    #[test]
+    fn fails_when_no_source_can_be_watched() {
+        let dir = TempDir::new("watch-none");
+        let result = watch(
+            vec![dir.join("a"), dir.join("b")],
+            scan::ALL,
+            scan::Options::default(),
+            |_| {},
+        );
+        assert_eq!(
+            result.err(),
+            Some("Failed to watch any wallpaper folder".to_string())
+        );
+    }
+}